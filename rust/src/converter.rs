@@ -0,0 +1,647 @@
+use std::{collections::BTreeMap, marker::PhantomData, rc::Rc};
+
+use foxglove::{
+    Encode, Schema,
+    schemas::{GeoJson, Grid, LaserScan, LocationFix, RawAudio, RawImage},
+};
+use foxglove_data_loader::{InitializationBuilder, LinkedSchema};
+
+use crate::{
+    hdf5::{Dataset, DatasetType},
+    json::{self, NamedColumns},
+    mapping::ConverterKind,
+    messages::{
+        Geolocation, GridGeometry, LaserScanParameters, MessageEncoding, RawFloatDataset,
+        RawIntegerDataset, frame_id, serialize_float_grid, serialize_float_raw, serialize_geojson,
+        serialize_integer_raw, serialize_laser_scan, serialize_location_fix,
+        serialize_mono_raw_image, serialize_raw_audio, serialize_rgb8_raw_image,
+    },
+};
+
+pub type DatasetMap = BTreeMap<String, Dataset>;
+
+/// Settings of a topic from the mapping rules and the file's attributes, kept apart from the
+/// attributes of its dataset.
+#[derive(Debug, Clone, Default)]
+pub struct TopicOptions {
+    /// Frame of the messages, instead of the dataset's `frame_id` attribute.
+    pub frame_id: Option<String>,
+    /// Encoding of generic datasets, when a mapping rule or an attribute sets one.
+    pub encoding: Option<MessageEncoding>,
+    /// Whether image frames are stored as `(width, height)`, like NWB `ImageSeries`.
+    pub transposed_images: bool,
+}
+
+impl TopicOptions {
+    pub fn frame_id(&self, dataset: &Dataset) -> String {
+        match &self.frame_id {
+            Some(frame_id) => frame_id.clone(),
+            None => frame_id(dataset),
+        }
+    }
+}
+
+type SerializeFn = Box<dyn Fn(u64, &Dataset) -> anyhow::Result<Vec<u8>>>;
+
+type PlanResult = anyhow::Result<Rc<dyn Plan>>;
+
+/// How the rows of one dataset are published: the schema of the channel and the serialization of
+/// each row.
+pub trait Plan {
+    /// Add the schema of the messages to the initialization.
+    fn add_schema(&self, init: &mut InitializationBuilder) -> anyhow::Result<LinkedSchema>;
+
+    fn serialize(&self, index: u64, dataset: &Dataset) -> anyhow::Result<Vec<u8>>;
+}
+
+/// Messages with a schema from [`Encode`].
+struct EncodePlan<T> {
+    serialize: SerializeFn,
+    _schema: PhantomData<T>,
+}
+
+impl<T: Encode> Plan for EncodePlan<T> {
+    fn add_schema(&self, init: &mut InitializationBuilder) -> anyhow::Result<LinkedSchema> {
+        init.add_encode::<T>()
+    }
+
+    fn serialize(&self, index: u64, dataset: &Dataset) -> anyhow::Result<Vec<u8>> {
+        (self.serialize)(index, dataset)
+    }
+}
+
+fn encode_plan<T: Encode + 'static>(
+    serialize: impl Fn(u64, &Dataset) -> anyhow::Result<Vec<u8>> + 'static,
+) -> Rc<dyn Plan> {
+    Rc::new(EncodePlan::<T> {
+        serialize: Box::new(serialize),
+        _schema: PhantomData,
+    })
+}
+
+/// JSON messages with a generated JSON schema.
+struct JsonPlan {
+    schema: Schema,
+    serialize: SerializeFn,
+}
+
+impl Plan for JsonPlan {
+    fn add_schema(&self, init: &mut InitializationBuilder) -> anyhow::Result<LinkedSchema> {
+        Ok(init
+            .add_schema(self.schema.clone())
+            .message_encoding("json"))
+    }
+
+    fn serialize(&self, index: u64, dataset: &Dataset) -> anyhow::Result<Vec<u8>> {
+        (self.serialize)(index, dataset)
+    }
+}
+
+pub fn json_plan(
+    schema: Schema,
+    serialize: impl Fn(u64, &Dataset) -> anyhow::Result<Vec<u8>> + 'static,
+) -> Rc<dyn Plan> {
+    Rc::new(JsonPlan {
+        schema,
+        serialize: Box::new(serialize),
+    })
+}
+
+/// Publishes datasets as one kind of message.
+pub trait Converter {
+    fn kind(&self) -> ConverterKind;
+
+    /// Appended to the dataset's topic name. Converters without a suffix publish on the dataset's
+    /// own topic, so only the first of them matching a dataset is used.
+    fn topic_suffix(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Whether the dataset looks meant for this converter, so it is used without a mapping rule
+    /// naming it.
+    fn detect(&self, _dataset: &Dataset, _datasets: &DatasetMap) -> bool {
+        true
+    }
+
+    /// Plan publishing the dataset, `None` when the converter can't handle it, or an error when
+    /// the dataset is meant for the converter but can't be read.
+    fn matches(
+        &self,
+        dataset: &Dataset,
+        datasets: &DatasetMap,
+        options: &TopicOptions,
+    ) -> anyhow::Result<Option<Rc<dyn Plan>>>;
+
+    /// The datasets supported by the converter, shown when a mapping rule names a converter which
+    /// doesn't match.
+    fn requirements(&self) -> &'static str;
+}
+
+/// Numeric columns published as named fields of JSON messages.
+struct FieldsConverter;
+
+impl Converter for FieldsConverter {
+    fn kind(&self) -> ConverterKind {
+        ConverterKind::Fields
+    }
+
+    fn matches(
+        &self,
+        dataset: &Dataset,
+        datasets: &DatasetMap,
+        options: &TopicOptions,
+    ) -> anyhow::Result<Option<Rc<dyn Plan>>> {
+        // fields are JSON messages, so datasets asking for protobuf are left to the raw converter
+        if options.encoding == Some(MessageEncoding::Protobuf) {
+            return Ok(None);
+        }
+
+        let Some(columns) = NamedColumns::from_dataset(dataset, datasets) else {
+            return Ok(None);
+        };
+
+        Ok(Some(json_plan(
+            columns.schema(dataset)?,
+            move |index, dataset| json::serialize_named_columns(index, dataset, &columns),
+        )))
+    }
+
+    fn requirements(&self) -> &'static str {
+        "Named fields need a numeric dataset with one or two dimensions, without the protobuf encoding."
+    }
+}
+
+/// Numeric rows published as `RawIntegerDataset`/`RawFloatDataset`, or their JSON equivalent
+/// with the coordinates of the axes when the topic's encoding is `json`.
+struct RawConverter;
+
+impl Converter for RawConverter {
+    fn kind(&self) -> ConverterKind {
+        ConverterKind::Raw
+    }
+
+    fn matches(
+        &self,
+        dataset: &Dataset,
+        datasets: &DatasetMap,
+        options: &TopicOptions,
+    ) -> anyhow::Result<Option<Rc<dyn Plan>>> {
+        let plan = match (options.encoding.unwrap_or_default(), dataset.type_) {
+            (MessageEncoding::Json, DatasetType::Integer | DatasetType::Float) => {
+                let coordinates = json::coordinates(dataset, datasets);
+
+                json_plan(
+                    json::dataset_schema(dataset, &coordinates)?,
+                    move |index, dataset| json::serialize_dataset(index, dataset, &coordinates),
+                )
+            }
+            (_, DatasetType::Integer) => encode_plan::<RawIntegerDataset>(serialize_integer_raw),
+            (_, DatasetType::Float) => encode_plan::<RawFloatDataset>(serialize_float_raw),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(plan))
+    }
+
+    fn requirements(&self) -> &'static str {
+        "Raw datasets need an integer or float dataset."
+    }
+}
+
+struct ImageConverter;
+
+impl Converter for ImageConverter {
+    fn kind(&self) -> ConverterKind {
+        ConverterKind::Image
+    }
+
+    fn topic_suffix(&self) -> Option<&'static str> {
+        Some("/as_image")
+    }
+
+    fn detect(&self, dataset: &Dataset, _datasets: &DatasetMap) -> bool {
+        dataset.is_image_topic()
+    }
+
+    fn matches(
+        &self,
+        dataset: &Dataset,
+        _datasets: &DatasetMap,
+        options: &TopicOptions,
+    ) -> anyhow::Result<Option<Rc<dyn Plan>>> {
+        let frame_id = options.frame_id(dataset);
+        let transposed = options.transposed_images;
+
+        let plan = match (dataset.type_, dataset.dimensions.len()) {
+            (DatasetType::Integer, 3) => encode_plan::<RawImage>(move |index, dataset| {
+                serialize_mono_raw_image(index, dataset, &frame_id, transposed)
+            }),
+            (DatasetType::Integer, 4) => encode_plan::<RawImage>(move |index, dataset| {
+                serialize_rgb8_raw_image(index, dataset, &frame_id, transposed)
+            }),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(plan))
+    }
+
+    fn requirements(&self) -> &'static str {
+        "Images need an integer dataset of (time, height, width) or (time, height, width, 3)."
+    }
+}
+
+struct GridConverter;
+
+impl Converter for GridConverter {
+    fn kind(&self) -> ConverterKind {
+        ConverterKind::Grid
+    }
+
+    fn topic_suffix(&self) -> Option<&'static str> {
+        Some("/as_grid")
+    }
+
+    fn detect(&self, dataset: &Dataset, _datasets: &DatasetMap) -> bool {
+        dataset.is_grid_topic()
+    }
+
+    fn matches(
+        &self,
+        dataset: &Dataset,
+        datasets: &DatasetMap,
+        options: &TopicOptions,
+    ) -> anyhow::Result<Option<Rc<dyn Plan>>> {
+        if !matches!(dataset.type_, DatasetType::Float) || dataset.dimensions.len() != 3 {
+            return Ok(None);
+        }
+
+        let geometry = GridGeometry::from_dataset(dataset, datasets, options.frame_id(dataset))?;
+
+        Ok(Some(encode_plan::<Grid>(move |index, dataset| {
+            serialize_float_grid(index, dataset, &geometry)
+        })))
+    }
+
+    fn requirements(&self) -> &'static str {
+        "Grids need a float dataset of (time, rows, columns)."
+    }
+}
+
+struct LaserScanConverter;
+
+impl Converter for LaserScanConverter {
+    fn kind(&self) -> ConverterKind {
+        ConverterKind::LaserScan
+    }
+
+    fn topic_suffix(&self) -> Option<&'static str> {
+        Some("/as_laser_scan")
+    }
+
+    fn detect(&self, dataset: &Dataset, _datasets: &DatasetMap) -> bool {
+        dataset.is_laser_scan_topic()
+    }
+
+    fn matches(
+        &self,
+        dataset: &Dataset,
+        datasets: &DatasetMap,
+        options: &TopicOptions,
+    ) -> anyhow::Result<Option<Rc<dyn Plan>>> {
+        if !matches!(dataset.type_, DatasetType::Float) || dataset.dimensions.len() != 2 {
+            return Ok(None);
+        }
+
+        let Some(parameters) =
+            LaserScanParameters::from_dataset(dataset, datasets, options.frame_id(dataset))
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(encode_plan::<LaserScan>(move |index, dataset| {
+            serialize_laser_scan(index, dataset, &parameters)
+        })))
+    }
+
+    fn requirements(&self) -> &'static str {
+        "Laser scans need a float dataset of (time, ranges) with angle_min and angle_max attributes."
+    }
+}
+
+struct AudioConverter;
+
+impl Converter for AudioConverter {
+    fn kind(&self) -> ConverterKind {
+        ConverterKind::Audio
+    }
+
+    fn topic_suffix(&self) -> Option<&'static str> {
+        Some("/as_audio")
+    }
+
+    fn matches(
+        &self,
+        dataset: &Dataset,
+        _datasets: &DatasetMap,
+        _options: &TopicOptions,
+    ) -> anyhow::Result<Option<Rc<dyn Plan>>> {
+        if !dataset.is_audio_topic() {
+            return Ok(None);
+        }
+
+        Ok(Some(encode_plan::<RawAudio>(serialize_raw_audio)))
+    }
+
+    fn requirements(&self) -> &'static str {
+        "Audio needs an int16 or float dataset of (time, samples) or (time, samples, channels) with a sample_rate attribute."
+    }
+}
+
+struct LocationFixConverter;
+
+impl Converter for LocationFixConverter {
+    fn kind(&self) -> ConverterKind {
+        ConverterKind::LocationFix
+    }
+
+    fn topic_suffix(&self) -> Option<&'static str> {
+        Some("/as_location_fix")
+    }
+
+    fn matches(
+        &self,
+        dataset: &Dataset,
+        datasets: &DatasetMap,
+        options: &TopicOptions,
+    ) -> anyhow::Result<Option<Rc<dyn Plan>>> {
+        if !matches!(dataset.type_, DatasetType::Float) || dataset.dimensions.len() > 2 {
+            return Ok(None);
+        }
+
+        let Some(geolocation) =
+            Geolocation::from_dataset(dataset, datasets, options.frame_id(dataset))
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(encode_plan::<LocationFix>(move |index, dataset| {
+            serialize_location_fix(index, dataset, &geolocation)
+        })))
+    }
+
+    fn requirements(&self) -> &'static str {
+        "Location fixes need a float latitude dataset of (time) or (time, positions) with a longitude dataset of the same shape in its group."
+    }
+}
+
+struct GeoJsonConverter;
+
+impl Converter for GeoJsonConverter {
+    fn kind(&self) -> ConverterKind {
+        ConverterKind::GeoJson
+    }
+
+    fn topic_suffix(&self) -> Option<&'static str> {
+        Some("/as_geojson")
+    }
+
+    fn matches(
+        &self,
+        dataset: &Dataset,
+        datasets: &DatasetMap,
+        options: &TopicOptions,
+    ) -> anyhow::Result<Option<Rc<dyn Plan>>> {
+        if !matches!(dataset.type_, DatasetType::Float) || dataset.dimensions.len() != 2 {
+            return Ok(None);
+        }
+
+        let Some(geolocation) =
+            Geolocation::from_dataset(dataset, datasets, options.frame_id(dataset))
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(encode_plan::<GeoJson>(move |index, dataset| {
+            serialize_geojson(index, dataset, &geolocation)
+        })))
+    }
+
+    fn requirements(&self) -> &'static str {
+        "GeoJSON needs a float latitude dataset of (time, positions) with a longitude dataset of the same shape in its group."
+    }
+}
+
+/// The coordinates of the axes of a dataset with dimension scales, whatever the encoding of the
+/// dataset's own topic.
+struct CoordinatesConverter;
+
+impl Converter for CoordinatesConverter {
+    fn kind(&self) -> ConverterKind {
+        ConverterKind::Coordinates
+    }
+
+    fn topic_suffix(&self) -> Option<&'static str> {
+        Some("/coordinates")
+    }
+
+    fn matches(
+        &self,
+        dataset: &Dataset,
+        datasets: &DatasetMap,
+        _options: &TopicOptions,
+    ) -> anyhow::Result<Option<Rc<dyn Plan>>> {
+        if !matches!(dataset.type_, DatasetType::Integer | DatasetType::Float) {
+            return Ok(None);
+        }
+
+        let coordinates = json::coordinates(dataset, datasets);
+
+        if coordinates.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(json_plan(
+            json::coordinates_schema(dataset, &coordinates)?,
+            move |_, _| json::serialize_coordinates(&coordinates),
+        )))
+    }
+
+    fn requirements(&self) -> &'static str {
+        "Coordinates need a numeric dataset with numeric dimension scales attached to the axes of its rows."
+    }
+}
+
+/// The converters available to the loader, in priority order.
+pub struct Registry {
+    converters: Vec<Box<dyn Converter>>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Self { converters: vec![] };
+
+        registry.register(FieldsConverter);
+        registry.register(RawConverter);
+        registry.register(ImageConverter);
+        registry.register(GridConverter);
+        registry.register(LaserScanConverter);
+        registry.register(AudioConverter);
+        registry.register(LocationFixConverter);
+        registry.register(GeoJsonConverter);
+        registry.register(CoordinatesConverter);
+
+        registry
+    }
+}
+
+impl Registry {
+    /// Add a converter, consulted after the ones already registered.
+    pub fn register(&mut self, converter: impl Converter + 'static) {
+        self.converters.push(Box::new(converter));
+    }
+
+    pub fn get(&self, kind: ConverterKind) -> Option<&dyn Converter> {
+        self.converters
+            .iter()
+            .find(|x| x.kind() == kind)
+            .map(|x| x.as_ref())
+    }
+
+    /// Converters used for a dataset when no mapping rule names one: the first converter
+    /// publishing on the dataset's own topic, plus every converter publishing a derived topic.
+    /// Converters failing to read a dataset meant for them are returned with their error.
+    pub fn detect(
+        &self,
+        dataset: &Dataset,
+        datasets: &DatasetMap,
+        options: &TopicOptions,
+    ) -> Vec<(&dyn Converter, PlanResult)> {
+        let mut out = vec![];
+        let mut has_topic = false;
+
+        for converter in self.converters.iter() {
+            let is_derived = converter.topic_suffix().is_some();
+
+            if (!is_derived && has_topic) || !converter.detect(dataset, datasets) {
+                continue;
+            }
+
+            let plan = match converter.matches(dataset, datasets, options) {
+                Ok(Some(plan)) => Ok(plan),
+                Ok(None) => continue,
+                Err(e) => Err(e),
+            };
+
+            has_topic |= !is_derived && plan.is_ok();
+            out.push((converter.as_ref(), plan));
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hdf5::Attribute;
+
+    fn detected(
+        dataset: &Dataset,
+        datasets: &DatasetMap,
+    ) -> Vec<(ConverterKind, Option<&'static str>)> {
+        Registry::default()
+            .detect(dataset, datasets, &TopicOptions::default())
+            .into_iter()
+            .map(|(converter, plan)| {
+                assert!(plan.is_ok(), "{:?}", converter.kind());
+                (converter.kind(), converter.topic_suffix())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn first_converter_takes_the_topic() {
+        let dataset = Dataset::in_memory("/imu/accel", DatasetType::Float, &[10, 3]);
+
+        assert_eq!(
+            detected(&dataset, &DatasetMap::new()),
+            [(ConverterKind::Fields, None)]
+        );
+
+        let protobuf = TopicOptions {
+            encoding: Some(MessageEncoding::Protobuf),
+            ..Default::default()
+        };
+        let registry = Registry::default();
+        let kinds: Vec<_> = registry
+            .detect(&dataset, &DatasetMap::new(), &protobuf)
+            .into_iter()
+            .map(|(converter, _)| converter.kind())
+            .collect();
+
+        assert_eq!(kinds, [ConverterKind::Raw]);
+    }
+
+    #[test]
+    fn derived_topics_follow_the_topic() {
+        let image = Dataset::in_memory("/camera/image", DatasetType::Integer, &[5, 480, 640]);
+
+        assert_eq!(
+            detected(&image, &DatasetMap::new()),
+            [
+                (ConverterKind::Raw, None),
+                (ConverterKind::Image, Some("/as_image"))
+            ]
+        );
+
+        let mut map = Dataset::in_memory("/map/elevation", DatasetType::Float, &[5, 100, 200]);
+
+        // only maps with a cell size are published as grids without a rule
+        assert_eq!(
+            detected(&map, &DatasetMap::new()),
+            [(ConverterKind::Raw, None)]
+        );
+
+        map.attrs
+            .insert("cell_size".to_string(), Attribute::Float(vec![0.5]));
+
+        assert_eq!(
+            detected(&map, &DatasetMap::new()),
+            [
+                (ConverterKind::Raw, None),
+                (ConverterKind::Grid, Some("/as_grid"))
+            ]
+        );
+    }
+
+    #[test]
+    fn no_converter_for_strings() {
+        let names = Dataset::in_memory("/names", DatasetType::String, &[10]);
+
+        assert!(detected(&names, &DatasetMap::new()).is_empty());
+    }
+
+    #[test]
+    fn positions_are_published_as_location_fixes() {
+        let latitude = Dataset::in_memory("/swath/lat", DatasetType::Float, &[10, 60]);
+        let longitude = Dataset::in_memory("/swath/lon", DatasetType::Float, &[10, 60]);
+        let datasets = DatasetMap::from([
+            (latitude.name.clone(), latitude.clone()),
+            (longitude.name.clone(), longitude),
+        ]);
+
+        let kinds: Vec<_> = detected(&latitude, &datasets)
+            .into_iter()
+            .map(|(kind, _)| kind)
+            .collect();
+        assert!(kinds.contains(&ConverterKind::LocationFix));
+        assert!(kinds.contains(&ConverterKind::GeoJson));
+
+        // without a longitude there is nothing to place
+        let kinds: Vec<_> = detected(&latitude, &DatasetMap::new())
+            .into_iter()
+            .map(|(kind, _)| kind)
+            .collect();
+        assert!(!kinds.contains(&ConverterKind::LocationFix));
+    }
+}
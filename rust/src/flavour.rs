@@ -0,0 +1,1134 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    hdf_eos::{self, StructMetadata},
+    hdf5::{Attribute, Dataset, DatasetType, Dimension, Hdf5File, Scale},
+    mapping::{ConverterKind, Mapping, Rule, TimeScale, TimeUnit},
+};
+
+/// NWB series of images, whose `data` is `(frame, x, y)` or `(frame, x, y, 3)`.
+const IMAGE_SERIES: [&str; 5] = [
+    "ImageSeries",
+    "ImageMaskSeries",
+    "OpticalSeries",
+    "TwoPhotonSeries",
+    "OnePhotonSeries",
+];
+
+/// Conventions of the application which wrote a file, on top of plain HDF5.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Flavour {
+    #[default]
+    Hdf5,
+    /// MATLAB v7.3 `.mat` files, HDF5 with a 512 byte user block holding the MAT-file header.
+    /// HDF5 finds the superblock after the user block on its own.
+    Matlab,
+    /// NetCDF-4 files, whose dimensions are stored as dimension scales.
+    NetCdf,
+    /// HDF-EOS5 files, with their swaths and grids under the `HDFEOS` group.
+    HdfEos {
+        metadata: StructMetadata,
+        /// Nanoseconds since the unix epoch of the start of the granule, the time of grids, when
+        /// the file holds it.
+        start: Option<i64>,
+    },
+    /// Neurodata Without Borders files, whose `TimeSeries` groups hold a `data` dataset timed
+    /// in seconds from the file's reference time.
+    Nwb {
+        /// Nanoseconds since the unix epoch of the file's `timestamps_reference_time`.
+        reference_time: i64,
+    },
+    /// Robot learning datasets like robomimic and LeRobot, with `demo_N` or `episode_N` groups
+    /// of steps and no wall clock time. Episodes are laid out one after the other.
+    Episodes {
+        /// Steps per second.
+        rate: f64,
+        /// Nanoseconds from the start of the timeline to the first step of each episode group.
+        starts: BTreeMap<String, i64>,
+    },
+    /// pandas `HDFStore` files, with a group for each DataFrame.
+    Pandas {
+        /// Groups holding a DataFrame, in the fixed or table format.
+        frames: Vec<String>,
+    },
+}
+
+/// Steps per second of episodes which don't record their control rate, the default of
+/// robosuite used by robomimic.
+const DEFAULT_EPISODE_RATE: f64 = 20.0;
+
+/// Rows of a dataset recorded at a fixed rate, without a timestamps dataset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sampling {
+    /// Nanoseconds since the unix epoch of the first row.
+    pub start: i64,
+    /// Rows per second.
+    pub rate: f64,
+}
+
+impl Flavour {
+    pub fn detect(
+        path: &str,
+        file: &Hdf5File,
+        root_attrs: &BTreeMap<String, Attribute>,
+        datasets: &BTreeMap<String, Dataset>,
+    ) -> Self {
+        let is_matlab = path.ends_with(".mat")
+            || datasets
+                .values()
+                .any(|x| x.attrs.contains_key("MATLAB_class"));
+
+        let is_hdf_eos = path.ends_with(".he5")
+            || datasets
+                .keys()
+                .any(|x| x.starts_with("/HDFEOS/") || x.starts_with("/HDFEOS INFORMATION/"));
+
+        let is_netcdf = path.ends_with(".nc")
+            || path.ends_with(".nc4")
+            || root_attrs.contains_key("_NCProperties")
+            || datasets
+                .values()
+                .any(|x| x.attrs.contains_key("_Netcdf4Dimid"));
+
+        let is_nwb = path.ends_with(".nwb")
+            || root_attrs.contains_key("nwb_version")
+            || root_attrs.get("neurodata_type").and_then(Attribute::as_str) == Some("NWBFile");
+
+        if is_nwb {
+            // older files only have a session start time, which timestamps are relative to
+            let reference_time = ["/timestamps_reference_time", "/session_start_time"]
+                .iter()
+                .filter_map(|x| datasets.get(*x)?.read_strings().ok())
+                .find_map(|x| parse_datetime(x.first()?))
+                .unwrap_or_default();
+
+            Flavour::Nwb { reference_time }
+        } else if is_matlab {
+            Flavour::Matlab
+        } else if is_hdf_eos {
+            let metadata = StructMetadata::read(datasets);
+            let start = granule_start(file, datasets, &metadata);
+
+            Flavour::HdfEos { metadata, start }
+        } else if is_netcdf {
+            Flavour::NetCdf
+        } else if let frames = pandas_frames(file, datasets)
+            && !frames.is_empty()
+        {
+            Flavour::Pandas { frames }
+        } else if let Some(episodes) = Self::detect_episodes(file, datasets) {
+            episodes
+        } else {
+            Flavour::Hdf5
+        }
+    }
+
+    /// Episodes of robomimic and LeRobot datasets, which hold the rate in the attributes of the
+    /// group of the episodes: the `env_args` of robomimic's `data` group, or LeRobot's `fps` and
+    /// `total` count. Files timing their datasets by `.timestamp` datasets aren't episodes.
+    fn detect_episodes(file: &Hdf5File, datasets: &BTreeMap<String, Dataset>) -> Option<Self> {
+        if datasets.keys().any(|x| x.ends_with(".timestamp")) {
+            return None;
+        }
+
+        let groups: BTreeSet<(u64, &str)> = datasets
+            .keys()
+            .filter_map(|x| episode_of(x))
+            .map(|(group, index)| (index, group))
+            .collect();
+
+        let parents: BTreeSet<&str> = groups
+            .iter()
+            .filter_map(|(_, group)| Some(group.rsplit_once('/')?.0))
+            .collect();
+
+        // LeRobot stores the rate as `fps`, robomimic as the `control_freq` of the environment
+        let rate = parents.into_iter().find_map(|parent| {
+            let attrs = file.get_object_attrs(if parent.is_empty() { "/" } else { parent });
+
+            if let Some(env_args) = attrs.get("env_args").and_then(Attribute::as_str)
+                && parent.rsplit('/').next() == Some("data")
+            {
+                let env_args: serde_json::Value = serde_json::from_str(env_args).ok()?;
+                return Some(env_args["env_kwargs"]["control_freq"].as_f64());
+            }
+
+            if attrs.contains_key("fps") || attrs.contains_key("total") {
+                return Some(attrs.get("fps").and_then(Attribute::as_f64));
+            }
+
+            None
+        })?;
+
+        let rate = rate.filter(|x| *x > 0.0).unwrap_or(DEFAULT_EPISODE_RATE);
+
+        let mut starts = BTreeMap::new();
+        let mut start = 0;
+
+        for (_, group) in groups {
+            starts.insert(group.to_string(), start);
+            start += (episode_steps(group, datasets) as f64 / rate * 1e9) as i64;
+        }
+
+        Some(Flavour::Episodes { rate, starts })
+    }
+
+    /// Names of the datasets holding the timestamps of every dataset in their group. MATLAB
+    /// structs are groups, with a `t` or `time` field.
+    pub fn group_timestamp_names(&self) -> &'static [&'static str] {
+        match self {
+            Flavour::Hdf5
+            | Flavour::NetCdf
+            | Flavour::HdfEos { .. }
+            | Flavour::Episodes { .. }
+            | Flavour::Pandas { .. } => &["timestamp"],
+            Flavour::Matlab => &["timestamp", "t", "time"],
+            Flavour::Nwb { .. } => &["timestamp", "timestamps"],
+        }
+    }
+
+    /// Rules applying the flavour's conventions, overridden by the rules of the file and of
+    /// mapping files. NWB `TimeSeries` are published on the topic of their group, and
+    /// `ImageSeries` as images.
+    pub fn rules(&self, file: &Hdf5File, datasets: &BTreeMap<String, Dataset>) -> Mapping {
+        let mut mapping = Mapping::default();
+
+        match self {
+            Flavour::Nwb { .. } => self.nwb_rules(&mut mapping, file, datasets),
+            Flavour::Episodes { .. } => {
+                // camera observations are named after the camera, like `agentview_rgb`
+                let images = datasets.values().filter(|x| {
+                    episode_of(&x.name).is_some()
+                        && matches!(x.type_, DatasetType::Integer)
+                        && matches!(x.stored_dimensions[..], [_, _, _, 3])
+                });
+
+                for dataset in images {
+                    mapping.rules.push(Rule {
+                        pattern: dataset.name.clone(),
+                        converter: Some(ConverterKind::Image),
+                        ..Default::default()
+                    });
+                }
+            }
+            _ => {}
+        }
+
+        mapping
+    }
+
+    fn nwb_rules(
+        &self,
+        mapping: &mut Mapping,
+        file: &Hdf5File,
+        datasets: &BTreeMap<String, Dataset>,
+    ) {
+        for name in datasets.keys() {
+            let Some(group) = name.strip_suffix("/data") else {
+                continue;
+            };
+
+            let attrs = file.get_object_attrs(group);
+
+            let Some(neurodata_type) = attrs.get("neurodata_type").and_then(Attribute::as_str)
+            else {
+                continue;
+            };
+
+            // image series can also reference frames in external files, with an empty `data`
+            let is_image = IMAGE_SERIES.contains(&neurodata_type)
+                && !datasets.contains_key(&format!("{group}/external_file"));
+
+            mapping.rules.push(Rule {
+                pattern: name.clone(),
+                topic: Some(group.to_string()),
+                converter: is_image.then_some(ConverterKind::Image),
+                ..Default::default()
+            });
+        }
+    }
+
+    /// Whether the flavour publishes a group on the group's own topic, like the `data` of NWB
+    /// `TimeSeries`, so its datasets aren't merged into another topic of that name.
+    pub fn publishes_group(&self, group: &str, datasets: &BTreeMap<String, Dataset>) -> bool {
+        matches!(self, Flavour::Nwb { .. }) && datasets.contains_key(&format!("{group}/data"))
+    }
+
+    /// Unit of timestamp datasets when no mapping rule or `units` attribute sets one.
+    pub fn time_unit(&self) -> TimeUnit {
+        match self {
+            Flavour::Hdf5 | Flavour::Pandas { .. } => TimeUnit::Ms,
+            Flavour::Matlab
+            | Flavour::NetCdf
+            | Flavour::HdfEos { .. }
+            | Flavour::Nwb { .. }
+            | Flavour::Episodes { .. } => TimeUnit::S,
+        }
+    }
+
+    /// Unit and origin of the values of a timestamp dataset, from a CF style `units` attribute
+    /// like `seconds since 1970-01-01 00:00:00`, on the time `scale` of the values, resolved by
+    /// the caller from a mapping rule or the `time_scale` attribute. Values on the GPS or TAI
+    /// scale without units are in seconds.
+    pub fn time_base(&self, timestamp_dataset: &Dataset, scale: Option<TimeScale>) -> TimeBase {
+        let base = timestamp_dataset
+            .attrs
+            .get("units")
+            .and_then(Attribute::as_str)
+            .and_then(TimeBase::from_units)
+            .unwrap_or_else(|| match self {
+                _ if scale.is_some() => TimeUnit::S.into(),
+                Flavour::Nwb { reference_time } => TimeBase {
+                    nanos: TimeUnit::S.nanos(),
+                    epoch: Some(*reference_time),
+                    scale: TimeScale::Utc,
+                },
+                Flavour::HdfEos { .. } => TAI93,
+                // LeRobot timestamps count from the start of their episode
+                Flavour::Episodes { starts, .. } => TimeBase {
+                    nanos: TimeUnit::S.nanos(),
+                    epoch: Some(
+                        episode_of(&timestamp_dataset.name)
+                            .and_then(|(group, _)| starts.get(group).copied())
+                            .unwrap_or_default(),
+                    ),
+                    scale: TimeScale::Utc,
+                },
+                _ => self.time_unit().into(),
+            });
+
+        match scale {
+            Some(scale) => base.on_scale(scale),
+            None => base,
+        }
+    }
+
+    /// Timing of datasets recorded at a fixed rate, which have no timestamps dataset: NWB series
+    /// with a `starting_time` and its `rate`, and the steps of episodes.
+    pub fn sampling(
+        &self,
+        dataset: &Dataset,
+        datasets: &BTreeMap<String, Dataset>,
+    ) -> Option<Sampling> {
+        match self {
+            Flavour::Nwb { reference_time } => {
+                let (group, _) = dataset.name.rsplit_once('/')?;
+                let starting_time = datasets.get(&format!("{group}/starting_time"))?;
+                let rate = starting_time
+                    .attrs
+                    .get("rate")
+                    .and_then(Attribute::as_f64)?;
+
+                let (start, _) = starting_time.read::<f64>().ok()?;
+                let start = reference_time + (start.first()? * 1e9) as i64;
+
+                (rate > 0.0).then_some(Sampling { start, rate })
+            }
+            Flavour::Episodes { rate, starts } => {
+                let (group, _) = episode_of(&dataset.name)?;
+
+                Some(Sampling {
+                    start: *starts.get(group)?,
+                    rate: *rate,
+                })
+            }
+            // a grid is a single map of the granule
+            Flavour::HdfEos { metadata, start } => {
+                metadata.grid_of(&dataset.name)?;
+
+                Some(Sampling {
+                    start: (*start)?,
+                    rate: 1.0,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the dataset only supports the structure of the file, and isn't published.
+    pub fn is_hidden(&self, dataset: &Dataset) -> bool {
+        // dimensions, with or without a coordinate variable, are read through the variables
+        // using them
+        if is_dimension_scale(dataset) {
+            return true;
+        }
+
+        match self {
+            Flavour::Hdf5 => false,
+            // published as a whole by `frames`
+            Flavour::Pandas { frames } => frames
+                .iter()
+                .any(|x| dataset.name.starts_with(&format!("{x}/"))),
+            Flavour::Matlab => {
+                // the elements of cell arrays are stored under `#refs#` and published through
+                // the cell arrays
+                let is_internal = dataset.name.starts_with("/#");
+
+                let class = dataset
+                    .attrs
+                    .get("MATLAB_class")
+                    .and_then(Attribute::as_str);
+
+                is_internal
+                    || matches!(class, Some("char" | "cell"))
+                    || dataset.attrs.contains_key("MATLAB_empty")
+            }
+            Flavour::NetCdf => false,
+            // the time of swaths is read through the fields it times
+            Flavour::HdfEos { metadata, .. } => {
+                dataset.name.starts_with("/HDFEOS INFORMATION/")
+                    || metadata.time_of(&dataset.name).as_ref() == Some(&dataset.name)
+            }
+            // metadata of the session and the format, and the timing of series
+            Flavour::Nwb { .. } => {
+                let (group, basename) = dataset.name.rsplit_once('/').unwrap_or_default();
+
+                group.is_empty()
+                    || ["/specifications", "/general"]
+                        .iter()
+                        .any(|x| dataset.name.starts_with(&format!("{x}/")))
+                    || matches!(basename, "starting_time" | "external_file")
+            }
+            // robomimic lists the episodes of each split under `mask`, and repeats every
+            // observation of the next step under `next_obs`
+            Flavour::Episodes { .. } => {
+                dataset.name.starts_with("/mask/") || dataset.name.contains("/next_obs/")
+            }
+        }
+    }
+
+    /// The coordinate variable holding the timestamps of a dataset and the axis it indexes,
+    /// among the dimension scales attached to its axes, or the `Time` field of its HDF-EOS swath.
+    pub fn time_coordinate<'a>(
+        &self,
+        dataset: &Dataset,
+        datasets: &'a BTreeMap<String, Dataset>,
+    ) -> Option<(usize, &'a Dataset)> {
+        if let Flavour::HdfEos { metadata, .. } = self
+            && let Some(time) = metadata.time_of(&dataset.name)
+        {
+            return Some((0, datasets.get(&time)?));
+        }
+
+        let dimensions = dataset.dimension_scales(datasets);
+
+        // the time axis of variables recorded over time is usually their first dimension
+        let time_axes: Vec<usize> = (0..dimensions.len())
+            .filter(|x| dimensions[*x].scale.is_some() && is_time_dimension(&dimensions[*x]))
+            .collect();
+
+        let axis = match time_axes[..] {
+            [0, ..] => 0,
+            [.., axis] => axis,
+            [] => return None,
+        };
+
+        Some((axis, dimensions[axis].scale?))
+    }
+
+    /// Groups published as a single topic with a field for each column, like pandas DataFrames.
+    pub fn frames(&self) -> &[String] {
+        match self {
+            Flavour::Pandas { frames } => frames,
+            _ => &[],
+        }
+    }
+
+    /// Whether image frames are stored as `(width, height)`, like the `(frame, x, y)` data of
+    /// NWB `ImageSeries`, rather than `(height, width)`.
+    pub fn transposes_images(&self) -> bool {
+        matches!(self, Flavour::Nwb { .. })
+    }
+
+    /// Whether the dataset's axes are stored in column major order.
+    pub fn is_column_major(&self, dataset: &Dataset) -> bool {
+        match self {
+            Flavour::Matlab => dataset.attrs.contains_key("MATLAB_class"),
+            Flavour::Hdf5
+            | Flavour::NetCdf
+            | Flavour::HdfEos { .. }
+            | Flavour::Nwb { .. }
+            | Flavour::Episodes { .. }
+            | Flavour::Pandas { .. } => false,
+        }
+    }
+
+    /// Conversion of the dataset's stored values, from the NWB `conversion` and `offset`
+    /// attributes of `TimeSeries` data.
+    pub fn scale(&self, dataset: &Dataset) -> Option<Scale> {
+        let Flavour::Nwb { .. } = self else {
+            return None;
+        };
+
+        if !dataset.name.ends_with("/data")
+            || !matches!(dataset.type_, DatasetType::Integer | DatasetType::Float)
+        {
+            return None;
+        }
+
+        let attr = |name| dataset.attrs.get(name).and_then(Attribute::as_f64);
+
+        let scale = Scale {
+            factor: attr("conversion").unwrap_or(1.0),
+            offset: attr("offset").unwrap_or(0.0),
+        };
+
+        (scale.factor != 1.0 || scale.offset != 0.0).then_some(scale)
+    }
+
+    /// Apply the flavour's layout and scaling to a dataset with `rows` timestamps. MATLAB
+    /// stores an `Nx3` matrix as `(3, N)`, so rows are indexed along the last axis matching the
+    /// number of timestamps. HDF-EOS grid fields are a single map placed by their grid.
+    pub fn prepare(&self, dataset: &mut Dataset, rows: u64) {
+        if let Some(scale) = self.scale(dataset) {
+            dataset.set_scale(scale);
+        }
+
+        if let Flavour::HdfEos { metadata, .. } = self
+            && let Some(grid) = metadata.grid_of(&dataset.name)
+            && dataset.stored_dimensions.len() == 2
+        {
+            let (cell_size, origin) = grid.geometry();
+
+            for (name, value) in [("cell_size", cell_size), ("origin", origin)] {
+                dataset
+                    .attrs
+                    .entry(name.to_string())
+                    .or_insert_with(|| Attribute::Float(value.to_vec()));
+            }
+
+            dataset.set_single_row();
+        }
+
+        if !self.is_column_major(dataset) {
+            return;
+        }
+
+        let time_axis = dataset
+            .stored_dimensions
+            .iter()
+            .rposition(|x| *x == rows)
+            .unwrap_or(dataset.stored_dimensions.len().saturating_sub(1));
+
+        dataset.set_layout(time_axis, true);
+    }
+
+    /// Datasets published through others, like the elements of MATLAB cell arrays, which are
+    /// named `{cell}/{index}` and read from the datasets the cell array references.
+    pub fn linked_datasets(
+        &self,
+        datasets: &BTreeMap<String, Dataset>,
+    ) -> BTreeMap<String, Dataset> {
+        let mut out = BTreeMap::new();
+
+        // the columns of PyTables tables are read as datasets of their own
+        if let Flavour::Pandas { frames } = self {
+            for frame in frames {
+                let Some(table) = datasets.get(&format!("{frame}/table")) else {
+                    continue;
+                };
+
+                out.extend(table.members().into_iter().map(|x| (x.name.clone(), x)));
+            }
+
+            return out;
+        }
+
+        if *self != Flavour::Matlab {
+            return out;
+        }
+
+        let cells = datasets
+            .values()
+            .filter(|x| x.attrs.get("MATLAB_class").and_then(Attribute::as_str) == Some("cell"));
+
+        for cell in cells {
+            let Ok(references) = cell.read_references() else {
+                continue;
+            };
+
+            for (index, reference) in references.iter().enumerate() {
+                let Some(element) = datasets.get(reference) else {
+                    continue;
+                };
+
+                let name = format!("{}/{index}", cell.name);
+
+                out.insert(
+                    name.clone(),
+                    Dataset {
+                        name,
+                        ..element.clone()
+                    },
+                );
+            }
+        }
+
+        out
+    }
+}
+
+/// Start of an HDF-EOS granule, from the date in the file attributes of Aura products, the
+/// range beginning of the ECS core metadata, or the first time step of its swaths.
+fn granule_start(
+    file: &Hdf5File,
+    datasets: &BTreeMap<String, Dataset>,
+    metadata: &StructMetadata,
+) -> Option<i64> {
+    let attrs = file.get_object_attrs("/HDFEOS/ADDITIONAL/FILE_ATTRIBUTES");
+    let attr = |name| {
+        attrs
+            .get(name)
+            .and_then(Attribute::as_f64s)?
+            .first()
+            .copied()
+    };
+
+    if let (Some(year), Some(month), Some(day)) = (
+        attr("GranuleYear"),
+        attr("GranuleMonth"),
+        attr("GranuleDay"),
+    ) {
+        return Some(days_from_civil(year as i64, month as i64, day as i64) * 86_400_000_000_000);
+    }
+
+    // TAI93 seconds of the first scan
+    if let Some(x) = attr("TAI93At0zOfGranule") {
+        return Some(TAI93.timestamp_f64(x) as i64);
+    }
+
+    if let Some(x) = hdf_eos::range_beginning(datasets).and_then(|x| parse_datetime(&x)) {
+        return Some(x);
+    }
+
+    metadata
+        .swath_times()
+        .iter()
+        .filter_map(|x| datasets.get(x)?.read::<f64>().ok())
+        .flat_map(|(times, _)| times)
+        .filter(|x| x.is_finite() && *x > 0.0)
+        .min_by(f64::total_cmp)
+        .map(|x| TAI93.timestamp_f64(x) as i64)
+}
+
+/// Groups holding a DataFrame written by pandas, marked by their `pandas_type` attribute.
+fn pandas_frames(file: &Hdf5File, datasets: &BTreeMap<String, Dataset>) -> Vec<String> {
+    let groups: BTreeSet<&str> = datasets
+        .keys()
+        .filter_map(|x| x.rsplit_once('/'))
+        .filter(|(_, basename)| matches!(*basename, "axis1" | "table"))
+        .map(|(group, _)| group)
+        .collect();
+
+    groups
+        .into_iter()
+        .filter(|group| {
+            let attrs = file.get_object_attrs(group);
+            let pandas_type = attrs.get("pandas_type").and_then(Attribute::as_str);
+
+            matches!(pandas_type, Some("frame" | "frame_table"))
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+/// Number of steps of an episode: the rows of its actions or observations, or of its longest
+/// dataset when it has neither.
+fn episode_steps(group: &str, datasets: &BTreeMap<String, Dataset>) -> u64 {
+    let prefix = format!("{group}/");
+
+    let members: Vec<(&str, u64)> = datasets
+        .range(prefix.clone()..)
+        .take_while(|(name, _)| name.starts_with(&prefix))
+        .map(|(name, x)| {
+            let rows = x.stored_dimensions.first().copied().unwrap_or(1);
+            (&name[prefix.len()..], rows)
+        })
+        .collect();
+
+    let steps = members
+        .iter()
+        .find(|(name, _)| matches!(*name, "actions" | "action"))
+        .or_else(|| {
+            members
+                .iter()
+                .find(|(name, _)| name.starts_with("obs/") || name.starts_with("observation"))
+        });
+
+    match steps {
+        Some((_, rows)) => *rows,
+        None => members
+            .iter()
+            .map(|(_, rows)| *rows)
+            .max()
+            .unwrap_or_default(),
+    }
+}
+
+/// The `demo_N` or `episode_N` group containing a dataset, and the episode's index.
+fn episode_of(name: &str) -> Option<(&str, u64)> {
+    let mut end = 0;
+
+    for segment in name.split('/') {
+        end += segment.len();
+
+        let index = ["demo_", "episode_"]
+            .iter()
+            .find_map(|prefix| segment.strip_prefix(prefix)?.parse().ok());
+
+        if let Some(index) = index {
+            return Some((&name[..end], index));
+        }
+
+        end += 1;
+    }
+
+    None
+}
+
+fn is_dimension_scale(dataset: &Dataset) -> bool {
+    dataset.attrs.get("CLASS").and_then(Attribute::as_str) == Some("DIMENSION_SCALE")
+}
+
+/// Whether an axis is time, by its label or name, or by the CF conventions of its coordinate
+/// variable.
+fn is_time_dimension(dimension: &Dimension) -> bool {
+    // whole names only, so `exposure_time_bins` or `lifetime` aren't time
+    let is_time_name = |name: &str| {
+        matches!(
+            name.to_lowercase().as_str(),
+            "t" | "time" | "times" | "timestamp" | "timestamps" | "datetime"
+        )
+    };
+
+    dimension.name().is_some_and(|x| is_time_name(&x))
+        || dimension
+            .scale
+            .is_some_and(|x| is_cf_time(x) || x.name.rsplit('/').next().is_some_and(is_time_name))
+}
+
+fn is_cf_time(dataset: &Dataset) -> bool {
+    let attr = |name| dataset.attrs.get(name).and_then(Attribute::as_str);
+
+    attr("standard_name") == Some("time")
+        || attr("axis") == Some("T")
+        || attr("units").and_then(TimeBase::from_units).is_some()
+}
+
+/// The time scale of a timestamp dataset, from its `time_scale` attribute.
+pub fn time_scale(timestamp_dataset: &Dataset) -> Option<TimeScale> {
+    timestamp_dataset
+        .attrs
+        .get("time_scale")
+        .and_then(Attribute::as_str)?
+        .parse()
+        .ok()
+}
+
+/// Nanoseconds since the unix epoch of 1993-01-01, the origin of the TAI93 times of HDF-EOS.
+const TAI93_EPOCH: i64 = 725_846_400_000_000_000;
+
+/// The TAI93 times of HDF-EOS swaths, SI seconds since 1993-01-01 UTC, when TAI was 27 s ahead.
+const TAI93: TimeBase = TimeBase {
+    nanos: 1_000_000_000,
+    epoch: Some(TAI93_EPOCH + 27_000_000_000),
+    scale: TimeScale::Tai,
+};
+
+/// Seconds in a GPS week.
+const GPS_WEEK: f64 = 604_800.0;
+
+/// TAI - UTC in seconds from the first day of a month, for every leap second since 1972.
+const LEAP_SECONDS: [(i64, i64, i64); 28] = [
+    (1972, 1, 10),
+    (1972, 7, 11),
+    (1973, 1, 12),
+    (1974, 1, 13),
+    (1975, 1, 14),
+    (1976, 1, 15),
+    (1977, 1, 16),
+    (1978, 1, 17),
+    (1979, 1, 18),
+    (1980, 1, 19),
+    (1981, 7, 20),
+    (1982, 7, 21),
+    (1983, 7, 22),
+    (1985, 7, 23),
+    (1988, 1, 24),
+    (1990, 1, 25),
+    (1991, 1, 26),
+    (1992, 7, 27),
+    (1993, 7, 28),
+    (1994, 7, 29),
+    (1996, 1, 30),
+    (1997, 7, 31),
+    (1999, 1, 32),
+    (2006, 1, 33),
+    (2009, 1, 34),
+    (2012, 7, 35),
+    (2015, 7, 36),
+    (2017, 1, 37),
+];
+
+/// TAI - UTC in seconds at a time in nanoseconds since the unix epoch. Times before 1972 use the
+/// offset of 1972.
+fn leap_seconds(utc: i64) -> i64 {
+    LEAP_SECONDS
+        .iter()
+        .rev()
+        .find(|(year, month, _)| days_from_civil(*year, *month, 1) * 86_400_000_000_000 <= utc)
+        .map(|(_, _, offset)| *offset)
+        .unwrap_or(LEAP_SECONDS[0].2)
+}
+
+impl TimeScale {
+    /// Nanoseconds since the unix epoch of the value 0 of timestamps without an origin.
+    fn epoch(self) -> i64 {
+        match self {
+            TimeScale::Utc => 0,
+            TimeScale::Gps => days_from_civil(1980, 1, 6) * 86_400_000_000_000,
+            TimeScale::Tai => days_from_civil(1958, 1, 1) * 86_400_000_000_000,
+        }
+    }
+
+    /// Seconds the scale is ahead of UTC at a UTC time.
+    fn offset(self, utc: i64) -> i64 {
+        match self {
+            TimeScale::Utc => 0,
+            TimeScale::Gps => leap_seconds(utc) - 19,
+            TimeScale::Tai => leap_seconds(utc),
+        }
+    }
+
+    /// Convert nanoseconds on the scale, counted like unix time, to nanoseconds since the unix
+    /// epoch in UTC.
+    pub fn to_utc(self, nanos: i64) -> i64 {
+        // the offset is looked up again at the UTC time, in case a leap second lies between
+        let utc = nanos - self.offset(nanos) * 1_000_000_000;
+        nanos - self.offset(utc) * 1_000_000_000
+    }
+}
+
+/// Conversion of timestamp values to nanoseconds since the unix epoch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeBase {
+    /// Nanoseconds per unit of the values.
+    pub nanos: u64,
+    /// Nanoseconds from the unix epoch to the value 0, on the time scale, or the scale's own
+    /// epoch when the values have no origin.
+    pub epoch: Option<i64>,
+    pub scale: TimeScale,
+}
+
+impl From<TimeUnit> for TimeBase {
+    fn from(unit: TimeUnit) -> Self {
+        Self {
+            nanos: unit.nanos(),
+            epoch: None,
+            scale: TimeScale::Utc,
+        }
+    }
+}
+
+impl TimeBase {
+    /// Parse units like `days since 2000-1-1` or `seconds since 1970-01-01T00:00:00Z`.
+    pub fn from_units(units: &str) -> Option<Self> {
+        let (unit, origin) = units.trim().split_once(" since ")?;
+
+        let nanos = match unit.trim().to_lowercase().as_str() {
+            "days" | "day" | "d" => 86_400_000_000_000,
+            "hours" | "hour" | "hrs" | "hr" | "h" => 3_600_000_000_000,
+            "minutes" | "minute" | "mins" | "min" => 60_000_000_000,
+            "seconds" | "second" | "secs" | "sec" | "s" => 1_000_000_000,
+            "milliseconds" | "millisecond" | "msecs" | "msec" | "ms" => 1_000_000,
+            "microseconds" | "microsecond" | "usecs" | "usec" | "us" => 1_000,
+            "nanoseconds" | "nanosecond" | "nsecs" | "nsec" | "ns" => 1,
+            _ => return None,
+        };
+
+        let epoch = parse_datetime(origin)?;
+
+        Some(Self {
+            nanos,
+            epoch: Some(epoch),
+            scale: TimeScale::Utc,
+        })
+    }
+
+    /// Read the values on another time scale. Values without an origin count from the scale's
+    /// epoch, like GPS seconds from 1980-01-06.
+    pub fn on_scale(self, scale: TimeScale) -> Self {
+        Self { scale, ..self }
+    }
+
+    fn origin(&self) -> i64 {
+        self.epoch.unwrap_or(self.scale.epoch())
+    }
+
+    pub fn timestamp_f64(&self, value: f64) -> u64 {
+        let nanos = value * self.nanos as f64 + self.origin() as f64;
+        self.scale.to_utc(nanos as i64).max(0) as u64
+    }
+
+    pub fn timestamp_u64(&self, value: u64) -> u64 {
+        let nanos = (value as i128 * self.nanos as i128 + self.origin() as i128)
+            .clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+        self.scale.to_utc(nanos).max(0) as u64
+    }
+
+    /// The timestamp of a GPS week number and seconds into the week.
+    pub fn gps_week_timestamp(week: f64, seconds: f64) -> u64 {
+        let base = TimeBase::from(TimeUnit::S).on_scale(TimeScale::Gps);
+        base.timestamp_f64(week * GPS_WEEK + seconds)
+    }
+}
+
+/// Nanoseconds since the unix epoch of an ISO 8601 date and time, like `2000-1-1`,
+/// `1970-01-01 00:00:00` or `2018-05-01T10:00:00.000-07:00`. Times without a time zone are UTC.
+pub fn parse_datetime(value: &str) -> Option<i64> {
+    let value = value.trim().trim_end_matches("UTC").trim();
+
+    let (date, time) = value
+        .split_once(['T', ' '])
+        .map(|(date, time)| (date, time.trim()))
+        .unwrap_or((value, ""));
+
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let (Some(Ok(year)), Some(Ok(month)), Some(Ok(day))) = (date.next(), date.next(), date.next())
+    else {
+        return None;
+    };
+
+    let time = time.trim_end_matches('Z');
+    let (time, zone) = time.split_at(time.find(['+', '-']).unwrap_or(time.len()));
+
+    let mut seconds = 0.0;
+
+    for (part, scale) in time.trim().split(':').zip([3600.0, 60.0, 1.0]) {
+        if part.is_empty() {
+            break;
+        }
+
+        seconds += part.parse::<f64>().ok()? * scale;
+    }
+
+    // `+HH:MM`, `+HHMM` or `+HH` ahead of UTC
+    if let Some(sign) = zone.chars().next() {
+        let digits: String = zone.chars().filter(char::is_ascii_digit).collect();
+        let hours: f64 = digits.get(..2)?.parse().ok()?;
+        let minutes: f64 = digits
+            .get(2..)
+            .filter(|x| !x.is_empty())
+            .unwrap_or("0")
+            .parse()
+            .ok()?;
+
+        let offset = hours * 3600.0 + minutes * 60.0;
+        seconds -= if sign == '-' { -offset } else { offset };
+    }
+
+    Some(days_from_civil(year, month, day) * 86_400_000_000_000 + (seconds * 1e9) as i64)
+}
+
+/// Days from 1970-01-01 to a date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: i64 = 1_000_000_000;
+
+    fn utc(value: &str) -> i64 {
+        parse_datetime(value).unwrap()
+    }
+
+    #[test]
+    fn time_base_from_units() {
+        let base = TimeBase::from_units("days since 2000-1-1").unwrap();
+        assert_eq!(base.nanos, 86_400 * SECOND as u64);
+        assert_eq!(base.epoch, Some(946_684_800 * SECOND));
+        assert_eq!(
+            base.timestamp_f64(1.5),
+            (946_684_800 + 129_600) * SECOND as u64
+        );
+
+        let base = TimeBase::from_units("ms since 1970-01-01T00:00:01Z").unwrap();
+        assert_eq!(base.timestamp_u64(1_000), 2 * SECOND as u64);
+
+        let base = TimeBase::from_units("hours since 1970-01-02 00:00:00 UTC").unwrap();
+        assert_eq!(base.epoch, Some(86_400 * SECOND));
+
+        assert_eq!(TimeBase::from_units("fortnights since 2000-1-1"), None);
+        assert_eq!(TimeBase::from_units("seconds since yesterday"), None);
+        assert_eq!(TimeBase::from_units("seconds"), None);
+    }
+
+    #[test]
+    fn parse_datetimes() {
+        assert_eq!(parse_datetime("1970-01-01 00:00:00"), Some(0));
+        assert_eq!(parse_datetime("1970-1-2"), Some(86_400 * SECOND));
+        assert_eq!(parse_datetime("2000-1-1"), Some(946_684_800 * SECOND));
+        assert_eq!(
+            parse_datetime("2018-05-01T10:00:00.000-07:00"),
+            parse_datetime("2018-05-01T17:00:00Z")
+        );
+        assert_eq!(
+            parse_datetime("2018-05-01T10:00:00+0130"),
+            parse_datetime("2018-05-01T08:30:00")
+        );
+        assert_eq!(
+            parse_datetime("1970-01-01T00:00:01.5 UTC"),
+            Some(1_500_000_000)
+        );
+        assert_eq!(parse_datetime("1969-12-31"), Some(-86_400 * SECOND));
+        assert_eq!(parse_datetime("yesterday"), None);
+    }
+
+    #[test]
+    fn episodes_of_datasets() {
+        assert_eq!(
+            episode_of("/data/demo_3/actions"),
+            Some(("/data/demo_3", 3))
+        );
+        assert_eq!(
+            episode_of("/episode_12/observation/image"),
+            Some(("/episode_12", 12))
+        );
+        assert_eq!(episode_of("/data/demo_x/actions"), None);
+        assert_eq!(episode_of("/data/mask"), None);
+    }
+
+    #[test]
+    fn episodes_last_as_long_as_their_actions() {
+        let datasets: BTreeMap<String, Dataset> = [
+            ("/data/demo_0/actions", 50),
+            ("/data/demo_0/obs/image", 51),
+            ("/data/demo_1/rewards", 30),
+            ("/data/demo_1/states", 40),
+        ]
+        .into_iter()
+        .map(|(name, rows)| {
+            (
+                name.to_string(),
+                Dataset::in_memory(name, DatasetType::Float, &[rows]),
+            )
+        })
+        .collect();
+
+        assert_eq!(episode_steps("/data/demo_0", &datasets), 50);
+        assert_eq!(episode_steps("/data/demo_1", &datasets), 40);
+        assert_eq!(episode_steps("/data/demo_2", &datasets), 0);
+    }
+
+    #[test]
+    fn time_coordinates_on_any_axis() {
+        let reference = |name: &str| Attribute::Vlen(vec![Attribute::Reference(name.to_string())]);
+
+        let mut time = Dataset::in_memory("/time", DatasetType::Float, &[100]);
+        time.attrs.insert(
+            "units".to_string(),
+            Attribute::Str("seconds since 1970-01-01".to_string()),
+        );
+
+        let mut temperature = Dataset::in_memory("/temperature", DatasetType::Float, &[10, 100]);
+        temperature.attrs.insert(
+            "DIMENSION_LIST".to_string(),
+            Attribute::Vlen(vec![reference("/x"), reference("/time")]),
+        );
+
+        let datasets = BTreeMap::from([
+            (
+                "/x".to_string(),
+                Dataset::in_memory("/x", DatasetType::Float, &[10]),
+            ),
+            ("/time".to_string(), time),
+        ]);
+
+        let coordinate = Flavour::Hdf5.time_coordinate(&temperature, &datasets);
+        assert_eq!(
+            coordinate.map(|(axis, x)| (axis, x.name.as_str())),
+            Some((1, "/time"))
+        );
+
+        temperature.attrs.insert(
+            "DIMENSION_LABELS".to_string(),
+            Attribute::Strings(vec![String::new(), "t".to_string()]),
+        );
+
+        let dimensions = temperature.dimension_scales(&datasets);
+        assert_eq!(dimensions[0].name().as_deref(), Some("x"));
+        assert_eq!(dimensions[1].name().as_deref(), Some("t"));
+        assert!(is_time_dimension(&dimensions[1]));
+
+        // only whole names are time, not names containing it
+        temperature.attrs.insert(
+            "DIMENSION_LABELS".to_string(),
+            Attribute::Strings(vec!["lifetime".to_string(), "Time".to_string()]),
+        );
+
+        let dimensions = temperature.dimension_scales(&datasets);
+        assert!(!is_time_dimension(&dimensions[0]));
+        assert!(is_time_dimension(&dimensions[1]));
+    }
+
+    #[test]
+    fn leap_seconds_follow_the_table() {
+        assert_eq!(leap_seconds(0), 10);
+        assert_eq!(leap_seconds(utc("1980-01-06")), 19);
+        assert_eq!(leap_seconds(utc("2016-12-31T23:59:59")), 36);
+        assert_eq!(leap_seconds(utc("2017-01-01")), 37);
+    }
+
+    #[test]
+    fn to_utc_crosses_a_leap_second() {
+        let new_year = utc("2017-01-01");
+
+        assert_eq!(TimeScale::Utc.to_utc(new_year), new_year);
+        assert_eq!(
+            TimeScale::Gps.to_utc(new_year + 16 * SECOND),
+            new_year - SECOND
+        );
+        assert_eq!(TimeScale::Gps.to_utc(new_year + 18 * SECOND), new_year);
+        assert_eq!(TimeScale::Tai.to_utc(new_year + 37 * SECOND), new_year);
+    }
+
+    #[test]
+    fn gps_weeks_count_from_the_gps_epoch() {
+        assert_eq!(
+            TimeBase::gps_week_timestamp(0.0, 0.0),
+            utc("1980-01-06") as u64
+        );
+
+        // week 2000 started 18 leap seconds ahead of UTC
+        assert_eq!(
+            TimeBase::gps_week_timestamp(2000.0, 0.0),
+            utc("2018-05-05T23:59:42") as u64
+        );
+    }
+
+    #[test]
+    fn time_scales_keep_explicit_origins() {
+        let unix = TimeBase::from_units("seconds since 1970-01-01").unwrap();
+        let new_year = utc("2017-01-01");
+
+        assert_eq!(
+            unix.on_scale(TimeScale::Gps)
+                .timestamp_u64((new_year / SECOND + 18) as u64),
+            new_year as u64
+        );
+
+        // values without an origin count from the epoch of their scale
+        let tai = TimeBase::from(TimeUnit::S).on_scale(TimeScale::Tai);
+        let seconds = (new_year - utc("1958-01-01")) / SECOND + 37;
+        assert_eq!(tai.timestamp_u64(seconds as u64), new_year as u64);
+    }
+}
@@ -19,15 +19,28 @@ use anyhow::bail;
 use foxglove::schemas::RawImage;
 // use foxglove_data_loader::console;
 use crate::error;
+use crate::wasm_vfs::FileSet;
 use hdf5_sys::*;
 
 static FAPL: OnceLock<i64> = OnceLock::new();
+static FAMILY_FAPL: OnceLock<i64> = OnceLock::new();
+static SPLIT_FAPL: OnceLock<i64> = OnceLock::new();
 
 #[cfg(not(target_arch = "wasm32"))]
 pub fn get_vfs_fapl() -> i64 {
     0
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+pub fn get_family_fapl() -> i64 {
+    0
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn get_split_fapl() -> i64 {
+    0
+}
+
 const H5Z_LZF: &'static H5Z_class1_t = &H5Z_class1_t {
     id: 32000,
     name: c"lzf".as_ptr(),
@@ -60,6 +73,30 @@ pub fn get_vfs_fapl() -> i64 {
     })
 }
 
+#[cfg(target_arch = "wasm32")]
+pub fn get_family_fapl() -> i64 {
+    *FAMILY_FAPL.get_or_init(|| unsafe {
+        let driver = H5FDregister(&crate::wasm_vfs::WASM_FAMILY_VFS as *const _);
+
+        let fapl = H5Pcreate(H5P_CLS_FILE_ACCESS_ID_g);
+        H5Pset_driver(fapl, driver, std::ptr::null());
+
+        fapl
+    })
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn get_split_fapl() -> i64 {
+    *SPLIT_FAPL.get_or_init(|| unsafe {
+        let driver = H5FDregister(&crate::wasm_vfs::WASM_SPLIT_VFS as *const _);
+
+        let fapl = H5Pcreate(H5P_CLS_FILE_ACCESS_ID_g);
+        H5Pset_driver(fapl, driver, std::ptr::null());
+
+        fapl
+    })
+}
+
 pub struct Hdf5File {
     handle: i64,
 }
@@ -113,53 +150,118 @@ pub struct Dataset {
     pub id: i64,
     pub root_id: i64,
     pub type_: DatasetType,
+    /// Size in bytes of one element of the dataset's stored type.
+    pub type_size: usize,
+    /// Whether the stored integer type is signed.
+    pub signed: bool,
     pub name: String,
     pub original_name: CString,
     pub attrs: BTreeMap<String, Attribute>,
     pub references: Vec<String>,
+    /// Shape of the dataset with the rows first, see [`Dataset::set_layout`].
     pub dimensions: Vec<u64>,
+    /// Shape of the dataset as stored in the file.
+    pub stored_dimensions: Vec<u64>,
+    /// Axis of the stored dataset indexed by timestamps, `None` when the whole dataset is one row,
+    /// see [`Dataset::set_single_row`].
+    pub time_axis: Option<usize>,
+    /// Whether the other axes are stored in column major order, like MATLAB arrays.
+    pub column_major: bool,
+    /// Conversion applied to the values read from the dataset, see [`Dataset::set_scale`].
+    pub scale: Option<Scale>,
+    /// The member read from a compound dataset, see [`Dataset::members`].
+    pub member: Option<Member>,
 }
 
-pub trait ToNativeType: Default + Clone {
-    fn native_type() -> i64;
+/// An axis of a dataset, see [`Dataset::dimension_scales`].
+#[derive(Debug, Clone)]
+pub struct Dimension<'a> {
+    pub label: Option<String>,
+    /// The coordinate values along the axis.
+    pub scale: Option<&'a Dataset>,
 }
 
-impl ToNativeType for u64 {
-    fn native_type() -> i64 {
-        unsafe { H5T_NATIVE_UINT64_g }
-    }
-}
+impl Dimension<'_> {
+    /// The name of the axis: its label, or the `NAME` of its dimension scale. NetCDF names
+    /// dimensions without a coordinate variable with a placeholder `NAME`, so the name of the
+    /// scale dataset is used for them.
+    pub fn name(&self) -> Option<String> {
+        if let Some(label) = &self.label {
+            return Some(label.clone());
+        }
+
+        let scale = self.scale?;
 
-impl ToNativeType for i64 {
-    fn native_type() -> i64 {
-        unsafe { H5T_NATIVE_INT64_g }
+        let name = scale
+            .attrs
+            .get("NAME")
+            .and_then(Attribute::as_str)
+            .filter(|x| !x.is_empty() && !x.starts_with("This is a netCDF dimension"));
+
+        match name {
+            Some(name) => Some(name.to_string()),
+            None => scale.name.rsplit('/').next().map(str::to_string),
+        }
     }
 }
 
-impl ToNativeType for u8 {
-    fn native_type() -> i64 {
-        unsafe { H5T_NATIVE_UINT8_g }
-    }
+/// A member of a compound dataset, like a column of a PyTables table.
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub name: CString,
+    /// Shape of the member's array type, empty for scalar members.
+    pub dimensions: Vec<u64>,
 }
 
-impl ToNativeType for u16 {
-    fn native_type() -> i64 {
-        unsafe { H5T_NATIVE_UINT16_g }
-    }
+/// Linear conversion of stored values to the values they represent, `value * factor + offset`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scale {
+    pub factor: f64,
+    pub offset: f64,
 }
 
-impl ToNativeType for f32 {
-    fn native_type() -> i64 {
-        unsafe { H5T_NATIVE_FLOAT_g }
+pub trait ToNativeType: Default + Clone {
+    fn native_type() -> i64;
+
+    fn to_f64(self) -> f64;
+
+    fn from_f64(value: f64) -> Self;
+
+    fn scaled(self, scale: &Scale) -> Self {
+        Self::from_f64(self.to_f64() * scale.factor + scale.offset)
     }
 }
 
-impl ToNativeType for f64 {
-    fn native_type() -> i64 {
-        unsafe { H5T_NATIVE_DOUBLE_g }
-    }
+macro_rules! native_types {
+    ($($type:ty => $native:ident),*) => {
+        $(
+            impl ToNativeType for $type {
+                fn native_type() -> i64 {
+                    unsafe { $native }
+                }
+
+                fn to_f64(self) -> f64 {
+                    self as f64
+                }
+
+                fn from_f64(value: f64) -> Self {
+                    value as Self
+                }
+            }
+        )*
+    };
 }
 
+native_types!(
+    u64 => H5T_NATIVE_UINT64_g,
+    i64 => H5T_NATIVE_INT64_g,
+    u8 => H5T_NATIVE_UINT8_g,
+    u16 => H5T_NATIVE_UINT16_g,
+    i16 => H5T_NATIVE_INT16_g,
+    f32 => H5T_NATIVE_FLOAT_g,
+    f64 => H5T_NATIVE_DOUBLE_g
+);
+
 impl Dataset {
     pub fn is_image_topic(&self) -> bool {
         let is_image_name = ["image", "camera", "video", "depth"]
@@ -173,6 +275,84 @@ impl Dataset {
         is_image_name && is_image_dimensions
     }
 
+    pub fn is_grid_topic(&self) -> bool {
+        // time + rows + columns of a scalar field, with a cell size marking it as a map. Other
+        // fields are published as grids by a mapping rule naming the grid converter.
+        let has_cell_size = ["cell_size", "resolution"]
+            .iter()
+            .any(|x| self.attrs.contains_key(*x));
+
+        matches!(self.type_, DatasetType::Float)
+            && self.dimensions.len() == 3
+            && has_cell_size
+            && !self.is_image_topic()
+            && !self.is_audio_topic()
+    }
+
+    pub fn is_audio_topic(&self) -> bool {
+        // time + samples, or
+        // time + samples + channels
+        let is_audio_dimensions = self.dimensions.len() == 2 || self.dimensions.len() == 3;
+
+        let is_sample_type = match self.type_ {
+            DatasetType::Integer => self.type_size == 2 && self.signed,
+            DatasetType::Float => true,
+            _ => false,
+        };
+
+        self.attrs.contains_key("sample_rate") && is_audio_dimensions && is_sample_type
+    }
+
+    pub fn is_laser_scan_topic(&self) -> bool {
+        // time + ranges, with the scan's angular span in attributes
+        matches!(self.type_, DatasetType::Float)
+            && self.dimensions.len() == 2
+            && self.attrs.contains_key("angle_min")
+            && self.attrs.contains_key("angle_max")
+    }
+
+    /// Index rows along `time_axis` of the stored dataset. The rows of column major datasets are
+    /// transposed and their singleton axes dropped, so `dimensions` is the shape seen by the
+    /// writer of the file, e.g. `(T, 3)` for an `Nx3` MATLAB matrix stored as `(3, T)`.
+    pub fn set_layout(&mut self, time_axis: usize, column_major: bool) {
+        let Some(rows) = self.stored_dimensions.get(time_axis).copied() else {
+            return;
+        };
+
+        let mut row: Vec<u64> = self
+            .stored_dimensions
+            .iter()
+            .enumerate()
+            .filter(|(axis, _)| *axis != time_axis)
+            .map(|(_, x)| *x)
+            .collect();
+
+        if column_major {
+            row.reverse();
+            row.retain(|x| *x != 1);
+        }
+
+        self.time_axis = Some(time_axis);
+        self.column_major = column_major;
+        self.dimensions = std::iter::once(rows).chain(row).collect();
+    }
+
+    /// Publish the whole dataset as a single row, like a gridded product without a time axis.
+    pub fn set_single_row(&mut self) {
+        self.time_axis = None;
+        self.dimensions = std::iter::once(1)
+            .chain(self.stored_dimensions.iter().copied())
+            .collect();
+    }
+
+    /// Read the values as floats converted by `scale`, like NWB data with a `conversion`
+    /// attribute.
+    pub fn set_scale(&mut self, scale: Scale) {
+        self.type_ = DatasetType::Float;
+        self.type_size = 8;
+        self.scale = Some(scale);
+    }
+
     pub fn read_at_index<T: ToNativeType>(&self, offset: u64) -> anyhow::Result<Vec<T>> {
         let dset_id = unsafe { H5Dopen2(self.root_id, self.original_name.as_ptr(), 0) };
         let dataspace_id = unsafe { H5Dget_space(dset_id) };
@@ -189,13 +369,18 @@ impl Dataset {
         };
 
         let mut offsets = vec![0_u64; ndims as _];
-        offsets[0] = offset;
-
         let mut counts = dims.clone();
-        counts[0] = 1;
 
-        let mut values =
-            vec![T::default(); counts[..].iter().copied().reduce(|a, b| a * b).unwrap() as usize];
+        if let Some(time_axis) = self.time_axis {
+            offsets[time_axis] = offset;
+            counts[time_axis] = 1;
+        }
+
+        let mut values = vec![
+            T::default();
+            (counts[..].iter().copied().reduce(|a, b| a * b).unwrap() * self.member_size())
+                as usize
+        ];
 
         let status = unsafe {
             H5Sselect_hyperslab(
@@ -215,16 +400,16 @@ impl Dataset {
         let memspace_id =
             unsafe { H5Screate_simple(ndims, counts.as_ptr() as *const _, std::ptr::null()) };
 
-        let status = unsafe {
+        let status = self.with_mem_type::<T, _>(|mem_type| unsafe {
             H5Dread(
                 dset_id,
-                T::native_type(),
+                mem_type,
                 memspace_id,
                 dataspace_id,
                 0,
                 values.as_mut_ptr() as *mut u64 as *mut _,
             )
-        };
+        });
 
         if status != 0 {
             bail!("failed to read at index");
@@ -236,9 +421,307 @@ impl Dataset {
             H5Dclose(dset_id);
         };
 
+        if let Some(scale) = &self.scale {
+            values = values.into_iter().map(|x| x.scaled(scale)).collect();
+        }
+
+        if self.column_major {
+            return Ok(transpose(values, &counts));
+        }
+
         Ok(values)
     }
 
+    /// Number of values in each element of the dataset, the size of the array members of
+    /// compound datasets.
+    fn member_size(&self) -> u64 {
+        self.member
+            .as_ref()
+            .map(|x| x.dimensions.iter().product())
+            .unwrap_or(1)
+    }
+
+    /// Call `read` with the memory type reading `T` values from the dataset. Members of compound
+    /// datasets are read through a compound type holding only that member, which HDF5 converts
+    /// the stored member to.
+    fn with_mem_type<T: ToNativeType, R>(&self, read: impl FnOnce(hid_t) -> R) -> R {
+        let Some(member) = &self.member else {
+            return read(T::native_type());
+        };
+
+        unsafe {
+            let element = if member.dimensions.is_empty() {
+                H5Tcopy(T::native_type())
+            } else {
+                H5Tarray_create2(
+                    T::native_type(),
+                    member.dimensions.len() as _,
+                    member.dimensions.as_ptr(),
+                )
+            };
+
+            let compound = H5Tcreate(H5T_class_t_H5T_COMPOUND, H5Tget_size(element));
+            H5Tinsert(compound, member.name.as_ptr(), 0, element);
+
+            let out = read(compound);
+
+            H5Tclose(compound);
+            H5Tclose(element);
+
+            out
+        }
+    }
+
+    /// The axes of the stored dataset within a row, in stored order.
+    pub fn row_axes(&self) -> Vec<usize> {
+        (0..self.stored_dimensions.len())
+            .filter(|x| Some(*x) != self.time_axis)
+            .collect()
+    }
+
+    /// The axes of the stored dataset, with their `DIMENSION_LABELS` label and the dimension
+    /// scale attached to them by `DIMENSION_LIST`.
+    pub fn dimension_scales<'a>(
+        &self,
+        datasets: &'a BTreeMap<String, Dataset>,
+    ) -> Vec<Dimension<'a>> {
+        let labels = self
+            .attrs
+            .get("DIMENSION_LABELS")
+            .and_then(Attribute::as_strings)
+            .unwrap_or_default();
+
+        let scales = match self.attrs.get("DIMENSION_LIST") {
+            Some(Attribute::Vlen(scales)) => scales.as_slice(),
+            _ => &[],
+        };
+
+        (0..self.stored_dimensions.len())
+            .map(|axis| Dimension {
+                label: labels.get(axis).filter(|x| !x.is_empty()).cloned(),
+                // each axis may have several scales, the first one is used
+                scale: scales.get(axis).and_then(|x| match x {
+                    Attribute::Reference(name) => datasets.get(name),
+                    Attribute::Vlen(names) => names.iter().find_map(|x| match x {
+                        Attribute::Reference(name) => datasets.get(name),
+                        _ => None,
+                    }),
+                    _ => None,
+                }),
+            })
+            .collect()
+    }
+
+    /// The integer and float members of a compound dataset, and arrays of them, as datasets
+    /// named `{dataset}/{member}`.
+    pub fn members(&self) -> Vec<Dataset> {
+        let mut out = vec![];
+
+        if !matches!(self.type_, DatasetType::Compound) {
+            return out;
+        }
+
+        unsafe {
+            let dset_id = H5Dopen2(self.root_id, self.original_name.as_ptr(), 0);
+            let file_type = H5Dget_type(dset_id);
+
+            for i in 0..H5Tget_nmembers(file_type).max(0) as u32 {
+                let name_ptr = H5Tget_member_name(file_type, i);
+                let name = CStr::from_ptr(name_ptr).to_owned();
+                H5free_memory(name_ptr as *mut _);
+
+                let member_type = H5Tget_member_type(file_type, i);
+                let mut dimensions = vec![];
+                let mut element_type = member_type;
+
+                if H5Tget_class(member_type) == H5T_class_t_H5T_ARRAY {
+                    dimensions = vec![0; H5Tget_array_ndims(member_type).max(0) as usize];
+                    H5Tget_array_dims2(member_type, dimensions.as_mut_ptr());
+                    element_type = H5Tget_super(member_type);
+                }
+
+                let type_ = DatasetType::from_type(H5Tget_class(element_type));
+                let type_size = H5Tget_size(element_type);
+                let signed = H5Tget_sign(element_type) == H5T_sign_t_H5T_SGN_2;
+
+                if element_type != member_type {
+                    H5Tclose(element_type);
+                }
+
+                H5Tclose(member_type);
+
+                if !matches!(type_, DatasetType::Integer | DatasetType::Float) {
+                    continue;
+                }
+
+                let shape: Vec<u64> = self
+                    .stored_dimensions
+                    .iter()
+                    .chain(dimensions.iter())
+                    .copied()
+                    .collect();
+
+                out.push(Dataset {
+                    name: format!("{}/{}", self.name, name.to_string_lossy()),
+                    type_,
+                    type_size,
+                    signed,
+                    attrs: BTreeMap::new(),
+                    references: vec![],
+                    dimensions: shape.clone(),
+                    stored_dimensions: shape,
+                    member: Some(Member { name, dimensions }),
+                    ..self.clone()
+                });
+            }
+
+            H5Tclose(file_type);
+            H5Dclose(dset_id);
+        }
+
+        out
+    }
+
+    /// The stored bytes of an attribute, for attributes which aren't plain strings or numbers
+    /// like the pickled attributes of PyTables.
+    pub fn read_attr_bytes(&self, name: &str) -> Option<Vec<u8>> {
+        let name = CString::new(name).ok()?;
+
+        unsafe {
+            let dset_id = H5Dopen2(self.root_id, self.original_name.as_ptr(), 0);
+            let attr_id = H5Aopen(dset_id, name.as_ptr(), 0);
+
+            if attr_id < 0 {
+                H5Dclose(dset_id);
+                return None;
+            }
+
+            let attr_type = H5Aget_type(attr_id);
+            let space_id = H5Aget_space(attr_id);
+            let count = H5Sget_simple_extent_npoints(space_id).max(0) as usize;
+
+            let mut bytes = vec![0_u8; H5Tget_size(attr_type) * count];
+            let status = H5Aread(attr_id, attr_type, bytes.as_mut_ptr() as *mut _);
+
+            H5Sclose(space_id);
+            H5Tclose(attr_type);
+            H5Aclose(attr_id);
+            H5Dclose(dset_id);
+
+            (status >= 0).then_some(bytes)
+        }
+    }
+
+    /// Paths of the objects referenced by a dataset of object references, like MATLAB cell
+    /// arrays.
+    pub fn read_references(&self) -> anyhow::Result<Vec<String>> {
+        let count = self.stored_dimensions.iter().product::<u64>() as usize;
+        let mut references: Vec<hobj_ref_t> = vec![0; count];
+
+        unsafe {
+            let dset_id = H5Dopen2(self.root_id, self.original_name.as_ptr(), 0);
+
+            let status = H5Dread(
+                dset_id,
+                H5T_STD_REF_OBJ_g,
+                0,
+                0,
+                0,
+                references.as_mut_ptr() as *mut _,
+            );
+
+            if status < 0 {
+                H5Dclose(dset_id);
+                bail!("failed to read references of {}", self.name);
+            }
+
+            let mut names = vec![];
+
+            for reference in references.iter() {
+                let obj = H5Rdereference2(
+                    dset_id,
+                    0,
+                    H5R_type_t_H5R_OBJECT1,
+                    reference as *const hobj_ref_t as *const _,
+                );
+
+                if obj < 0 {
+                    continue;
+                }
+
+                let name = object_name(obj);
+
+                H5Oclose(obj);
+
+                names.extend(name);
+            }
+
+            H5Dclose(dset_id);
+
+            Ok(names)
+        }
+    }
+
+    /// Source files of a virtual dataset, `.` standing for the dataset's own file. Other datasets
+    /// have none.
+    pub fn virtual_files(&self) -> Vec<String> {
+        let mut files = vec![];
+
+        unsafe {
+            let dset_id = H5Dopen2(self.root_id, self.original_name.as_ptr(), 0);
+            let dcpl = H5Dget_create_plist(dset_id);
+
+            let mut count = 0;
+
+            if H5Pget_layout(dcpl) == H5D_layout_t_H5D_VIRTUAL
+                && H5Pget_virtual_count(dcpl, &mut count) >= 0
+            {
+                for i in 0..count {
+                    let size = H5Pget_virtual_filename(dcpl, i, std::ptr::null_mut(), 0);
+
+                    if size < 0 {
+                        continue;
+                    }
+
+                    let mut name = vec![0_u8; size as usize + 1];
+                    H5Pget_virtual_filename(dcpl, i, name.as_mut_ptr() as *mut _, name.len());
+
+                    let name = CStr::from_bytes_until_nul(&name)
+                        .map(|x| x.to_string_lossy().to_string())
+                        .unwrap_or_default();
+
+                    if !files.contains(&name) {
+                        files.push(name);
+                    }
+                }
+            }
+
+            H5Pclose(dcpl);
+            H5Dclose(dset_id);
+        }
+
+        files
+    }
+
+    /// Read every element of a string dataset, fixed or variable length.
+    pub fn read_strings(&self) -> anyhow::Result<Vec<String>> {
+        let count = self.dimensions.iter().product::<u64>() as usize;
+
+        unsafe {
+            let dset_id = H5Dopen2(self.root_id, self.original_name.as_ptr(), 0);
+            let file_type = H5Dget_type(dset_id);
+
+            let values = read_strings(file_type, count, |mem_type, buf| {
+                H5Dread(dset_id, mem_type, 0, 0, 0, buf)
+            });
+
+            H5Tclose(file_type);
+            H5Dclose(dset_id);
+
+            values
+        }
+    }
+
     pub fn read<T: ToNativeType>(&self) -> anyhow::Result<(Vec<T>, Vec<u64>)> {
         let dset_id = unsafe { H5Dopen2(self.root_id, self.original_name.as_ptr(), 0) };
         let dataspace_id = unsafe { H5Dget_space(dset_id) };
@@ -262,8 +745,11 @@ impl Dataset {
         //     bail!("failed to read dims");
         // }
 
-        let mut values =
-            vec![T::default(); dims.iter().copied().reduce(|a, b| a * b).unwrap() as usize];
+        let mut values = vec![
+            T::default();
+            (dims.iter().copied().reduce(|a, b| a * b).unwrap() * self.member_size())
+                as usize
+        ];
 
         let offsets = vec![0_u64; ndims as _];
 
@@ -278,29 +764,27 @@ impl Dataset {
             )
         };
 
-        assert_eq!(status, 0);
-        // if status != 0 {
-        //     bail!("failed to select hyperslab");
-        // }
+        if status != 0 {
+            bail!("failed to select hyperslab");
+        }
 
         let memspace_id =
             unsafe { H5Screate_simple(ndims, dims.as_ptr() as *const _, std::ptr::null()) };
 
-        let status = unsafe {
+        let status = self.with_mem_type::<T, _>(|mem_type| unsafe {
             H5Dread(
                 dset_id,
-                T::native_type(),
+                mem_type,
                 memspace_id,
                 dataspace_id,
                 0,
                 values.as_mut_ptr() as *mut u64 as *mut _,
             )
-        };
+        });
 
-        assert_eq!(status, 0);
-        // if status != 0 {
-        //     bail!("failed to read data");
-        // }
+        if status != 0 {
+            bail!("failed to read data");
+        }
 
         unsafe {
             H5Sclose(memspace_id);
@@ -308,24 +792,162 @@ impl Dataset {
             H5Dclose(dset_id);
         };
 
+        let mut dims = dims;
+
+        if let Some(member) = &self.member {
+            dims.extend(&member.dimensions);
+        }
+
         Ok((values, dims))
     }
 }
 
+/// Reorder the elements of an array from row major to column major order, which is the row major
+/// order of the reversed shape.
+fn transpose<T: Clone>(values: Vec<T>, shape: &[u64]) -> Vec<T> {
+    if shape.iter().filter(|x| **x != 1).count() < 2 {
+        return values;
+    }
+
+    let mut strides = vec![1; shape.len()];
+
+    for axis in (0..shape.len() - 1).rev() {
+        strides[axis] = strides[axis + 1] * shape[axis + 1];
+    }
+
+    let mut index = vec![0; shape.len()];
+    let mut out = Vec::with_capacity(values.len());
+
+    for _ in 0..values.len() {
+        let offset: u64 = index.iter().zip(strides.iter()).map(|(i, x)| i * x).sum();
+        out.push(values[offset as usize].clone());
+
+        // the first axis varies fastest
+        for axis in 0..shape.len() {
+            index[axis] += 1;
+
+            if index[axis] < shape[axis] {
+                break;
+            }
+
+            index[axis] = 0;
+        }
+    }
+
+    out
+}
+
 #[derive(Default)]
 struct ObjectIterateData {
     datasets: BTreeMap<String, Dataset>,
     root_id: hid_t,
+    // path from the root of the object being visited, when it was reached through a link
+    prefix: String,
+}
+
+/// A link to an object stored in another file.
+#[derive(Debug, Clone)]
+pub struct ExternalLink {
+    /// Path of the link in this file.
+    pub path: String,
+    /// Name of the target file, as stored in the link.
+    pub file: String,
+    /// Path of the target object in the target file.
+    pub object: String,
+}
+
+unsafe extern "C" fn hdf5_link_visit_callback(
+    group: hid_t,
+    name: *const ::std::os::raw::c_char,
+    info: *const H5L_info2_t,
+    op_data: *mut ::std::os::raw::c_void,
+) -> herr_t {
+    let links: &mut Vec<ExternalLink> = unsafe { &mut *(op_data as *mut Vec<ExternalLink>) };
+    let info = unsafe { &*info };
+
+    if info.type_ != H5L_type_t_H5L_TYPE_EXTERNAL {
+        return 0;
+    }
+
+    unsafe {
+        let size = info.u.val_size;
+        let mut value = vec![0_u8; size];
+
+        if H5Lget_val(group, name, value.as_mut_ptr() as *mut _, size, 0) < 0 {
+            return 0;
+        }
+
+        let mut flags = 0;
+        let mut file = std::ptr::null();
+        let mut object = std::ptr::null();
+
+        if H5Lunpack_elink_val(
+            value.as_ptr() as *const _,
+            size,
+            &mut flags,
+            &mut file,
+            &mut object,
+        ) < 0
+        {
+            return 0;
+        }
+
+        let path = CStr::from_ptr(name).to_string_lossy();
+        let prefix = if path.starts_with('/') { "" } else { "/" };
+
+        links.push(ExternalLink {
+            path: format!("{prefix}{path}"),
+            file: CStr::from_ptr(file).to_string_lossy().to_string(),
+            object: CStr::from_ptr(object).to_string_lossy().to_string(),
+        });
+    }
+
+    0
 }
 
 #[derive(Debug, Clone)]
 pub enum Attribute {
     Str(String),
+    Strings(Vec<String>),
+    Integer(Vec<i64>),
+    Float(Vec<f64>),
     Vlen(Vec<Attribute>),
     Reference(String),
     Unknown(String),
 }
 
+impl Attribute {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Attribute::Str(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// String attribute values, a scalar string is a list of one.
+    pub fn as_strings(&self) -> Option<Vec<String>> {
+        match self {
+            Attribute::Str(value) => Some(vec![value.clone()]),
+            Attribute::Strings(values) => Some(values.clone()),
+            _ => None,
+        }
+    }
+
+    /// Numeric attribute values as floats, integers are converted.
+    pub fn as_f64s(&self) -> Option<Vec<f64>> {
+        match self {
+            Attribute::Integer(values) => Some(values.iter().map(|x| *x as f64).collect()),
+            Attribute::Float(values) => Some(values.clone()),
+            _ => None,
+        }
+    }
+
+    /// The first numeric value of the attribute, for scalar attributes.
+    pub fn as_f64(&self) -> Option<f64> {
+        self.as_f64s()?.first().copied()
+    }
+}
+
 #[derive(Default)]
 struct AttrIterateData {
     references: Vec<String>,
@@ -333,75 +955,149 @@ struct AttrIterateData {
     obj: hid_t,
 }
 
-fn read_string_attr(attr_id: hid_t) -> anyhow::Result<String> {
+/// Read `count` strings stored with `file_type`, where `read` fills a buffer using the memory type
+/// it is given. This is shared by attributes and datasets.
+fn read_strings(
+    file_type: hid_t,
+    count: usize,
+    read: impl FnOnce(hid_t, *mut c_void) -> herr_t,
+) -> anyhow::Result<Vec<String>> {
     unsafe {
-        // Inspect the attribute’s datatype
-        let attr_type = H5Aget_type(attr_id);
-        if H5Tget_class(attr_type) != H5T_class_t_H5T_STRING {
-            bail!("attribute is not a string");
+        if H5Tget_class(file_type) != H5T_class_t_H5T_STRING {
+            bail!("type is not a string");
         }
 
         // (Optional) capture charset & padding for trimming
-        let cset: H5T_cset_t = H5Tget_cset(attr_type);
-        let pad: H5T_str_t = H5Tget_strpad(attr_type);
+        let cset: H5T_cset_t = H5Tget_cset(file_type);
+        let pad: H5T_str_t = H5Tget_strpad(file_type);
 
         // Make a memory type we control (best practice).
         // For strings, copy C_S1 and configure either fixed size or VARIABLE.
         let mem_type = H5Tcopy(H5T_C_S1_g);
         H5Tset_cset(mem_type, cset); // preserve ASCII/UTF-8 flag
 
-        let is_vlen = H5Tis_variable_str(attr_type) > 0;
+        let is_vlen = H5Tis_variable_str(file_type) > 0;
         if is_vlen {
-            // Variable-length string: read as a pointer that HDF5 allocates.
+            // Variable-length string: read as pointers that HDF5 allocates.
             H5Tset_size(mem_type, H5T_VARIABLE as _);
-            let mut ptr: *mut c_char = std::ptr::null_mut();
-            let status = H5Aread(attr_id, mem_type, &mut ptr as *mut _ as *mut _);
+            let mut ptrs: Vec<*mut c_char> = vec![std::ptr::null_mut(); count];
+            let status = read(mem_type, ptrs.as_mut_ptr() as *mut _);
             if status < 0 {
-                bail!("H5Aread failed (vlen)");
+                bail!("read failed (vlen)");
             }
-            if ptr.is_null() {
-                return Ok(String::new());
+
+            let mut out = Vec::with_capacity(count);
+
+            for ptr in ptrs {
+                if ptr.is_null() {
+                    out.push(String::new());
+                    continue;
+                }
+                // Copy to Rust String (respects declared charset, but we’ll treat as UTF-8/ASCII)
+                out.push(CStr::from_ptr(ptr).to_string_lossy().into_owned());
+                // Free HDF5-owned memory
+                H5free_memory(ptr as *mut _);
             }
-            // Copy to Rust String (respects declared charset, but we’ll treat as UTF-8/ASCII)
-            let s = CStr::from_ptr(ptr).to_string_lossy().into_owned();
-            // Free HDF5-owned memory
-            H5free_memory(ptr as *mut _);
-            Ok(s)
+
+            Ok(out)
         } else {
             // Fixed-length: read into a byte buffer and trim padding
-            let n = H5Tget_size(attr_type) as usize;
+            let n = H5Tget_size(file_type) as usize;
             H5Tset_size(mem_type, n);
 
-            let mut buf: Vec<u8> = vec![0u8; n];
-            let status = H5Aread(attr_id, mem_type, buf.as_mut_ptr() as *mut _);
+            let mut buf: Vec<u8> = vec![0u8; n * count];
+            let status = read(mem_type, buf.as_mut_ptr() as *mut _);
             if status < 0 {
-                bail!("H5Aread failed (fixed)");
+                bail!("read failed (fixed)");
             }
 
-            // Trim according to declared padding
-            let trimmed = match pad {
-                H5T_str_t_H5T_STR_NULLTERM | H5T_str_t_H5T_STR_NULLPAD => {
-                    // stop at first NUL
-                    if let Some(z) = buf.iter().position(|&b| b == 0) {
-                        &buf[..z]
-                    } else {
-                        &buf[..]
-                    }
-                }
-                H5T_str_t_H5T_STR_SPACEPAD => {
-                    // rtrim spaces
-                    let mut end = buf.len();
-                    while end > 0 && buf[end - 1] == b' ' {
-                        end -= 1;
-                    }
-                    &buf[..end]
-                }
-                _ => &buf[..],
-            };
+            let out = buf
+                .chunks(n.max(1))
+                .map(|buf| {
+                    // Trim according to declared padding
+                    let trimmed = match pad {
+                        H5T_str_t_H5T_STR_NULLTERM | H5T_str_t_H5T_STR_NULLPAD => {
+                            // stop at first NUL
+                            if let Some(z) = buf.iter().position(|&b| b == 0) {
+                                &buf[..z]
+                            } else {
+                                buf
+                            }
+                        }
+                        H5T_str_t_H5T_STR_SPACEPAD => {
+                            // rtrim spaces
+                            let mut end = buf.len();
+                            while end > 0 && buf[end - 1] == b' ' {
+                                end -= 1;
+                            }
+                            &buf[..end]
+                        }
+                        _ => buf,
+                    };
+
+                    // Convert; if ASCII charset, this is still fine in UTF-8.
+                    String::from_utf8_lossy(trimmed).into_owned()
+                })
+                .collect();
+
+            Ok(out)
+        }
+    }
+}
+
+/// The path of an open object, of any length.
+fn object_name(obj: hid_t) -> Option<String> {
+    unsafe {
+        let size = H5Iget_name(obj, std::ptr::null_mut(), 0);
+
+        if size < 0 {
+            return None;
+        }
+
+        let mut name = vec![0_u8; size as usize + 1];
+
+        if H5Iget_name(obj, name.as_mut_ptr() as *mut _, name.len()) < 0 {
+            return None;
+        }
+
+        CStr::from_bytes_until_nul(&name)
+            .ok()
+            .map(|x| x.to_string_lossy().to_string())
+    }
+}
 
-            // Convert; if ASCII charset, this is still fine in UTF-8.
-            Ok(String::from_utf8_lossy(trimmed).into_owned())
+fn read_string_attr(attr_id: hid_t) -> anyhow::Result<Vec<String>> {
+    unsafe {
+        // Inspect the attribute’s datatype
+        let attr_type = H5Aget_type(attr_id);
+
+        let space_id = H5Aget_space(attr_id);
+        let count = H5Sget_simple_extent_npoints(space_id);
+        H5Sclose(space_id);
+
+        read_strings(attr_type, count.max(0) as usize, |mem_type, buf| {
+            H5Aread(attr_id, mem_type, buf)
+        })
+    }
+}
+
+fn read_numeric_attr<T: ToNativeType>(attr_id: hid_t) -> anyhow::Result<Vec<T>> {
+    unsafe {
+        let space_id = H5Aget_space(attr_id);
+        let count = H5Sget_simple_extent_npoints(space_id);
+        H5Sclose(space_id);
+
+        if count < 0 {
+            bail!("failed to get attribute size");
         }
+
+        let mut values = vec![T::default(); count as usize];
+        let status = H5Aread(attr_id, T::native_type(), values.as_mut_ptr() as *mut _);
+        if status < 0 {
+            bail!("H5Aread failed (numeric)");
+        }
+
+        Ok(values)
     }
 }
 
@@ -422,9 +1118,25 @@ unsafe extern "C" fn hfd5_object_attr_visit_callback(
     let name = name.to_string_lossy().to_string();
 
     match attr_class {
-        H5T_class_t_H5T_STRING => {
-            if let Ok(attr) = read_string_attr(attr_id) {
-                data.attrs.insert(name, Attribute::Str(attr));
+        H5T_class_t_H5T_STRING => match read_string_attr(attr_id).as_deref() {
+            Ok([attr]) => {
+                data.attrs.insert(name, Attribute::Str(attr.clone()));
+            }
+            Ok(attrs) => {
+                data.attrs.insert(name, Attribute::Strings(attrs.to_vec()));
+            }
+            Err(_) => {}
+        },
+
+        H5T_class_t_H5T_INTEGER => {
+            if let Ok(values) = read_numeric_attr::<i64>(attr_id) {
+                data.attrs.insert(name, Attribute::Integer(values));
+            }
+        }
+
+        H5T_class_t_H5T_FLOAT => {
+            if let Ok(values) = read_numeric_attr::<f64>(attr_id) {
+                data.attrs.insert(name, Attribute::Float(values));
             }
         }
 
@@ -456,15 +1168,15 @@ unsafe extern "C" fn hfd5_object_attr_visit_callback(
 
                     let source_obj = H5Rdereference2(obj_id, 0, 0, value.p as *mut _);
 
-                    let mut dataset_name_buf = vec![0_u8; 255];
-
-                    let len = H5Iget_name(source_obj, dataset_name_buf.as_mut_ptr() as *mut _, 255);
+                    if source_obj < 0 {
+                        continue;
+                    }
 
-                    let dataset_name = String::from_utf8_lossy(&dataset_name_buf[..len as _]);
+                    let dataset_name = object_name(source_obj);
 
                     H5Oclose(source_obj);
 
-                    attributes.push(Attribute::Reference(dataset_name.to_string()));
+                    attributes.extend(dataset_name.map(Attribute::Reference));
                 }
 
                 data.attrs.insert(name, Attribute::Vlen(attributes));
@@ -516,6 +1228,8 @@ unsafe extern "C" fn hdf5_object_visit_callback(
             let dataset_type = H5Dget_type(dset_id);
             let dataset_class = H5Tget_class(dataset_type);
             let type_ = DatasetType::from_type(dataset_class);
+            let type_size = H5Tget_size(dataset_type);
+            let signed = H5Tget_sign(dataset_type) == H5T_sign_t_H5T_SGN_2;
 
             let ndims = H5Sget_simple_extent_ndims(space_id);
 
@@ -538,9 +1252,17 @@ unsafe extern "C" fn hdf5_object_visit_callback(
 
             H5Dclose(dset_id);
 
-            let original_name =
-                CString::from_vec_with_nul(CStr::from_ptr(name).to_bytes_with_nul().to_vec())
-                    .unwrap();
+            // objects reached through a link are opened by their path from the root
+            let relative = CStr::from_ptr(name).to_string_lossy();
+
+            let original_name = match (data.prefix.as_str(), &*relative) {
+                ("", _) => {
+                    CString::from_vec_with_nul(CStr::from_ptr(name).to_bytes_with_nul().to_vec())
+                        .unwrap()
+                }
+                (prefix, ".") => CString::new(prefix).unwrap(),
+                (prefix, relative) => CString::new(format!("{prefix}/{relative}")).unwrap(),
+            };
             let name = original_name.to_string_lossy();
 
             let prefix = if name.starts_with('/') { "" } else { "/" };
@@ -554,9 +1276,16 @@ unsafe extern "C" fn hdf5_object_visit_callback(
                     id: dset_id,
                     root_id: data.root_id,
                     type_,
+                    type_size,
+                    signed,
                     name,
                     original_name,
                     dimensions: dims[..ndims as _].to_vec(),
+                    stored_dimensions: dims[..ndims as _].to_vec(),
+                    time_axis: Some(0),
+                    column_major: false,
+                    scale: None,
+                    member: None,
                     attrs: attrs.attrs,
                     references: attrs.references,
                 },
@@ -568,13 +1297,40 @@ unsafe extern "C" fn hdf5_object_visit_callback(
 }
 
 impl Hdf5File {
-    pub fn open(file: &str) -> anyhow::Result<Self> {
-        let file = CString::from_str(file)?;
+    pub fn open(path: &str) -> anyhow::Result<Self> {
+        let file = CString::from_str(path)?;
         let fapl_id = get_vfs_fapl();
 
         crate::error!("open file");
         let handle = unsafe { H5Fopen(file.as_ptr(), 0, fapl_id) };
 
+        if handle < 0 {
+            bail!("failed to open {path}");
+        }
+
+        Ok(Self { handle })
+    }
+
+    /// Open the files of a file family or split file as one file.
+    pub fn open_set(files: &FileSet) -> anyhow::Result<Self> {
+        let (members, fapl_id) = match files {
+            FileSet::Single(path) => return Self::open(path),
+            FileSet::Family(members) => (members.clone(), get_family_fapl()),
+            FileSet::Split { meta, raw } => {
+                (vec![(0, meta.clone()), (1, raw.clone())], get_split_fapl())
+            }
+        };
+
+        let file = CString::from_str(files.path())?;
+
+        crate::wasm_vfs::register_members(members);
+
+        let handle = unsafe { H5Fopen(file.as_ptr(), 0, fapl_id) };
+
+        if handle < 0 {
+            bail!("failed to open {}", files.path());
+        }
+
         Ok(Self { handle })
     }
 
@@ -585,6 +1341,60 @@ impl Hdf5File {
         info
     }
 
+    /// Attributes of the file's root group.
+    pub fn get_attrs(&self) -> BTreeMap<String, Attribute> {
+        let mut attrs = AttrIterateData {
+            obj: self.handle,
+            ..Default::default()
+        };
+
+        unsafe {
+            H5Aiterate2(
+                self.handle,
+                H5_index_t_H5_INDEX_NAME,
+                H5_iter_order_t_H5_ITER_INC,
+                std::ptr::null_mut(),
+                Some(hfd5_object_attr_visit_callback),
+                &mut attrs as *mut AttrIterateData as *mut _,
+            );
+        }
+
+        attrs.attrs
+    }
+
+    /// Attributes of the group or dataset at `path`.
+    pub fn get_object_attrs(&self, path: &str) -> BTreeMap<String, Attribute> {
+        let Ok(name) = CString::new(path) else {
+            return BTreeMap::new();
+        };
+
+        unsafe {
+            let obj = H5Oopen(self.handle, name.as_ptr(), 0);
+
+            if obj < 0 {
+                return BTreeMap::new();
+            }
+
+            let mut attrs = AttrIterateData {
+                obj,
+                ..Default::default()
+            };
+
+            H5Aiterate2(
+                obj,
+                H5_index_t_H5_INDEX_NAME,
+                H5_iter_order_t_H5_ITER_INC,
+                std::ptr::null_mut(),
+                Some(hfd5_object_attr_visit_callback),
+                &mut attrs as *mut AttrIterateData as *mut _,
+            );
+
+            H5Oclose(obj);
+
+            attrs.attrs
+        }
+    }
+
     pub fn get_datasets(&self) -> BTreeMap<String, Dataset> {
         let mut data = ObjectIterateData::default();
         data.root_id = self.handle;
@@ -604,6 +1414,56 @@ impl Hdf5File {
         data.datasets
     }
 
+    /// Links to objects in other files, which are not visited by [`Hdf5File::get_datasets`].
+    pub fn external_links(&self) -> Vec<ExternalLink> {
+        let mut links: Vec<ExternalLink> = vec![];
+
+        unsafe {
+            H5Lvisit2(
+                self.handle,
+                H5_index_t_H5_INDEX_NAME,
+                H5_iter_order_t_H5_ITER_INC,
+                Some(hdf5_link_visit_callback),
+                &mut links as *mut Vec<ExternalLink> as *mut _,
+            );
+        }
+
+        links
+    }
+
+    /// Datasets reached through an external link, named by their path through the link.
+    pub fn get_linked_datasets(&self, link: &ExternalLink) -> BTreeMap<String, Dataset> {
+        let mut data = ObjectIterateData {
+            root_id: self.handle,
+            prefix: link.path.clone(),
+            ..Default::default()
+        };
+
+        let Ok(path) = CString::new(link.path.as_str()) else {
+            return data.datasets;
+        };
+
+        unsafe {
+            let obj = H5Oopen(self.handle, path.as_ptr(), 0);
+
+            if obj < 0 {
+                return data.datasets;
+            }
+
+            H5Ovisit1(
+                obj,
+                H5_index_t_H5_INDEX_NAME,
+                H5_iter_order_t_H5_ITER_INC,
+                Some(hdf5_object_visit_callback),
+                &mut data as *mut ObjectIterateData as *mut _,
+            );
+
+            H5Oclose(obj);
+        }
+
+        data.datasets
+    }
+
     pub fn links(&self) -> Vec<String> {
         let info = self.get_group_info();
         let mut links = Vec::with_capacity(info.nlinks as _);
@@ -650,3 +1510,83 @@ impl Hdf5File {
         links
     }
 }
+
+#[cfg(test)]
+impl Dataset {
+    /// A dataset which isn't backed by a file, for tests of the logic reading only its metadata.
+    pub(crate) fn in_memory(name: &str, type_: DatasetType, dimensions: &[u64]) -> Self {
+        Dataset {
+            id: -1,
+            root_id: -1,
+            type_,
+            type_size: 8,
+            signed: true,
+            name: name.to_string(),
+            original_name: CString::new(name).unwrap(),
+            attrs: BTreeMap::new(),
+            references: Vec::new(),
+            dimensions: dimensions.to_vec(),
+            stored_dimensions: dimensions.to_vec(),
+            time_axis: Some(0),
+            column_major: false,
+            scale: None,
+            member: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transpose_reverses_the_axes() {
+        // [[0, 1, 2], [3, 4, 5]] read as [[0, 3], [1, 4], [2, 5]]
+        assert_eq!(transpose((0..6).collect(), &[2, 3]), [0, 3, 1, 4, 2, 5]);
+        assert_eq!(transpose((0..3).collect(), &[1, 3]), [0, 1, 2]);
+
+        let values: Vec<u32> = (0..24).collect();
+        assert_eq!(transpose(values, &[2, 3, 4])[..4], [0, 12, 4, 16]);
+    }
+
+    #[test]
+    fn rows_follow_the_time_axis() {
+        let mut dataset = Dataset::in_memory("/temperature", DatasetType::Float, &[10, 20, 100]);
+        dataset.set_layout(2, false);
+        assert_eq!(dataset.dimensions, [100, 10, 20]);
+        assert_eq!(dataset.time_axis, Some(2));
+
+        // out of range axes are ignored
+        dataset.set_layout(5, false);
+        assert_eq!(dataset.time_axis, Some(2));
+    }
+
+    #[test]
+    fn column_major_layouts_drop_singleton_axes() {
+        // an Nx3 MATLAB matrix stored as (3, N)
+        let mut dataset = Dataset::in_memory("/pose", DatasetType::Float, &[3, 50]);
+        dataset.set_layout(1, true);
+        assert_eq!(dataset.dimensions, [50, 3]);
+        assert!(dataset.column_major);
+
+        let mut dataset = Dataset::in_memory("/t", DatasetType::Float, &[1, 50]);
+        dataset.set_layout(1, true);
+        assert_eq!(dataset.dimensions, [50]);
+
+        let mut dataset = Dataset::in_memory("/image", DatasetType::Integer, &[4, 3, 2, 10]);
+        dataset.set_layout(3, true);
+        assert_eq!(dataset.dimensions, [10, 2, 3, 4]);
+    }
+
+    #[test]
+    fn scaled_values() {
+        let scale = Scale {
+            factor: 0.5,
+            offset: -1.0,
+        };
+
+        assert_eq!(4.0f64.scaled(&scale), 1.0);
+        assert_eq!(10i16.scaled(&scale), 4);
+        assert_eq!(0u8.scaled(&scale), 0);
+    }
+}
@@ -0,0 +1,434 @@
+use std::collections::BTreeMap;
+
+use crate::hdf5::Dataset;
+
+/// The swaths and grids of an HDF-EOS5 file, from the ODL text of its
+/// `/HDFEOS INFORMATION/StructMetadata.0` dataset.
+///
+/// ```text
+/// GROUP=SwathStructure
+///     GROUP=SWATH_1
+///         SwathName="ColumnAmountO3"
+///         GROUP=GeoField
+///             OBJECT=GeoField_1
+///                 GeoFieldName="Time"
+///                 DimList=("nTimes")
+///             END_OBJECT=GeoField_1
+///         ...
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StructMetadata {
+    pub swaths: Vec<Swath>,
+    pub grids: Vec<Grid>,
+}
+
+/// Observations along the track of a satellite, one scan line per time step.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Swath {
+    pub name: String,
+    /// Fields under `Geolocation Fields`, like `Time`, `Latitude` and `Longitude`.
+    pub geo_fields: Vec<Field>,
+    pub data_fields: Vec<Field>,
+}
+
+/// Fields on a regular grid of a map projection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grid {
+    pub name: String,
+    pub columns: u64,
+    pub rows: u64,
+    /// Projected (x, y) coordinates of the upper left corner of the grid.
+    pub upper_left: [f64; 2],
+    /// Projected (x, y) coordinates of the lower right corner of the grid.
+    pub lower_right: [f64; 2],
+    /// GCTP projection, like `HE5_GCTP_GEO` for longitude and latitude.
+    pub projection: String,
+    pub data_fields: Vec<Field>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: String,
+    /// Names of the field's dimensions, like `nTimes` and `nXtrack`.
+    pub dimensions: Vec<String>,
+}
+
+impl StructMetadata {
+    /// Read the metadata of a file, which is split in `StructMetadata.0`, `StructMetadata.1`...
+    /// when longer than the 32000 characters of a single dataset.
+    pub fn read(datasets: &BTreeMap<String, Dataset>) -> Self {
+        Self::parse(&read_metadata(datasets, "StructMetadata"))
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let root = Node::parse(text);
+
+        let swaths = root
+            .child("SwathStructure")
+            .map(|x| x.children.iter())
+            .into_iter()
+            .flatten()
+            .filter_map(|(_, node)| {
+                Some(Swath {
+                    name: node.string("SwathName")?,
+                    geo_fields: node.fields("GeoField", "GeoFieldName"),
+                    data_fields: node.fields("DataField", "DataFieldName"),
+                })
+            })
+            .collect();
+
+        let grids = root
+            .child("GridStructure")
+            .map(|x| x.children.iter())
+            .into_iter()
+            .flatten()
+            .filter_map(|(_, node)| {
+                Some(Grid {
+                    name: node.string("GridName")?,
+                    columns: node.string("XDim")?.parse().ok()?,
+                    rows: node.string("YDim")?.parse().ok()?,
+                    upper_left: node.point("UpperLeftPointMtrs")?,
+                    lower_right: node.point("LowerRightMtrs")?,
+                    projection: node.string("Projection").unwrap_or_default(),
+                    data_fields: node.fields("DataField", "DataFieldName"),
+                })
+            })
+            .collect();
+
+        Self { swaths, grids }
+    }
+
+    /// The `Time` geolocation field timing a field of a swath, when the field's first dimension
+    /// is the dimension of the time steps.
+    pub fn time_of(&self, name: &str) -> Option<String> {
+        self.swaths.iter().find_map(|swath| {
+            let geo_path = |field: &Field| swath_path(&swath.name, "Geolocation Fields", field);
+            let data_path = |field: &Field| swath_path(&swath.name, "Data Fields", field);
+
+            let field = swath
+                .geo_fields
+                .iter()
+                .find(|x| geo_path(x) == name)
+                .or_else(|| swath.data_fields.iter().find(|x| data_path(x) == name))?;
+
+            let time = swath.geo_fields.iter().find(|x| x.name == "Time")?;
+
+            (field.dimensions.first() == time.dimensions.first()).then(|| geo_path(time))
+        })
+    }
+
+    /// Paths of the `Time` geolocation fields of the swaths.
+    pub fn swath_times(&self) -> Vec<String> {
+        self.swaths
+            .iter()
+            .filter_map(|swath| {
+                let time = swath.geo_fields.iter().find(|x| x.name == "Time")?;
+                Some(swath_path(&swath.name, "Geolocation Fields", time))
+            })
+            .collect()
+    }
+
+    /// The grid of a data field of a grid.
+    pub fn grid_of(&self, name: &str) -> Option<&Grid> {
+        self.grids.iter().find(|grid| {
+            grid.data_fields
+                .iter()
+                .any(|x| format!("/HDFEOS/GRIDS/{}/Data Fields/{}", grid.name, x.name) == name)
+        })
+    }
+}
+
+fn swath_path(swath: &str, group: &str, field: &Field) -> String {
+    format!("/HDFEOS/SWATHS/{swath}/{group}/{}", field.name)
+}
+
+impl Grid {
+    /// The cell size and the lower left corner of the grid in its projection, in (x, y) order.
+    /// Rows usually go from north to south, which is a negative y cell size.
+    pub fn geometry(&self) -> ([f64; 2], [f64; 2]) {
+        let [left, top] = self.upper_left.map(|x| self.coordinate(x));
+        let [right, bottom] = self.lower_right.map(|x| self.coordinate(x));
+
+        let cell_size = [
+            (right - left) / self.columns.max(1) as f64,
+            (bottom - top) / self.rows.max(1) as f64,
+        ];
+
+        (cell_size, [left.min(right), top.min(bottom)])
+    }
+
+    /// Geographic grids give their corners in packed degrees, minutes and seconds,
+    /// `DDDMMMSSS.SS`, other projections in meters.
+    fn coordinate(&self, value: f64) -> f64 {
+        if self.projection != "HE5_GCTP_GEO" {
+            return value;
+        }
+
+        let sign = value.signum();
+        let value = value.abs();
+        let degrees = (value / 1e6).trunc();
+        let minutes = ((value - degrees * 1e6) / 1e3).trunc();
+        let seconds = value - degrees * 1e6 - minutes * 1e3;
+
+        sign * (degrees + minutes / 60.0 + seconds / 3600.0)
+    }
+}
+
+/// The ODL text of a metadata dataset of `/HDFEOS INFORMATION`, which is split in `{name}.0`,
+/// `{name}.1`... when longer than the 32000 characters of a single dataset.
+fn read_metadata(datasets: &BTreeMap<String, Dataset>, name: &str) -> String {
+    let mut text = String::new();
+
+    for index in 0.. {
+        let Some(dataset) = datasets.get(&format!("/HDFEOS INFORMATION/{name}.{index}")) else {
+            break;
+        };
+
+        let Ok(strings) = dataset.read_strings() else {
+            break;
+        };
+
+        text.extend(strings);
+    }
+
+    text
+}
+
+/// Date and time of the start of the granule in the ECS inventory metadata of
+/// `/HDFEOS INFORMATION/CoreMetadata.0`, from its `RANGEBEGINNINGDATE` and `RANGEBEGINNINGTIME`
+/// objects, like `2004-10-01T00:00:00.000000`.
+pub fn range_beginning(datasets: &BTreeMap<String, Dataset>) -> Option<String> {
+    range_beginning_of(&read_metadata(datasets, "CoreMetadata"))
+}
+
+fn range_beginning_of(text: &str) -> Option<String> {
+    let root = Node::parse(text);
+    let value = |name| root.find(name)?.string("VALUE");
+
+    let date = value("RANGEBEGINNINGDATE")?;
+
+    Some(match value("RANGEBEGINNINGTIME") {
+        Some(time) => format!("{date}T{time}"),
+        None => date,
+    })
+}
+
+/// A `GROUP` or `OBJECT` of ODL text, with its `KEY=VALUE` statements.
+#[derive(Debug, Default)]
+struct Node {
+    values: BTreeMap<String, String>,
+    children: Vec<(String, Node)>,
+}
+
+impl Node {
+    fn parse(text: &str) -> Self {
+        let mut stack = vec![(String::new(), Node::default())];
+
+        for line in text.lines() {
+            let Some((key, value)) = line.trim().split_once('=') else {
+                continue;
+            };
+
+            let (key, value) = (key.trim(), value.trim());
+
+            match key {
+                "GROUP" | "OBJECT" => stack.push((value.to_string(), Node::default())),
+                "END_GROUP" | "END_OBJECT" if stack.len() > 1 => {
+                    let node = stack.pop().unwrap();
+                    stack.last_mut().unwrap().1.children.push(node);
+                }
+                _ => {
+                    stack
+                        .last_mut()
+                        .unwrap()
+                        .1
+                        .values
+                        .insert(key.to_string(), value.to_string());
+                }
+            }
+        }
+
+        // unterminated groups are kept
+        while stack.len() > 1 {
+            let node = stack.pop().unwrap();
+            stack.last_mut().unwrap().1.children.push(node);
+        }
+
+        stack.pop().unwrap().1
+    }
+
+    fn child(&self, name: &str) -> Option<&Node> {
+        self.children
+            .iter()
+            .find(|(x, _)| x == name)
+            .map(|(_, node)| node)
+    }
+
+    /// The first group or object of the given name, at any depth.
+    fn find(&self, name: &str) -> Option<&Node> {
+        self.children.iter().find_map(|(x, node)| match x == name {
+            true => Some(node),
+            false => node.find(name),
+        })
+    }
+
+    fn string(&self, key: &str) -> Option<String> {
+        Some(self.values.get(key)?.trim_matches('"').to_string())
+    }
+
+    /// A list value, like `("nTimes","nXtrack")`.
+    fn list(&self, key: &str) -> Vec<String> {
+        self.values
+            .get(key)
+            .map(|x| x.trim_start_matches('(').trim_end_matches(')'))
+            .into_iter()
+            .flat_map(|x| x.split(','))
+            .map(|x| x.trim().trim_matches('"').to_string())
+            .filter(|x| !x.is_empty())
+            .collect()
+    }
+
+    fn point(&self, key: &str) -> Option<[f64; 2]> {
+        match self.list(key).as_slice() {
+            [x, y] => Some([x.parse().ok()?, y.parse().ok()?]),
+            _ => None,
+        }
+    }
+
+    fn fields(&self, group: &str, name_key: &str) -> Vec<Field> {
+        self.child(group)
+            .map(|x| x.children.iter())
+            .into_iter()
+            .flatten()
+            .filter_map(|(_, node)| {
+                Some(Field {
+                    name: node.string(name_key)?,
+                    dimensions: node.list("DimList"),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STRUCT_METADATA: &str = r#"
+GROUP=SwathStructure
+    GROUP=SWATH_1
+        SwathName="ColumnAmountO3"
+        GROUP=GeoField
+            OBJECT=GeoField_1
+                GeoFieldName="Time"
+                DimList=("nTimes")
+            END_OBJECT=GeoField_1
+            OBJECT=GeoField_2
+                GeoFieldName="Latitude"
+                DimList=("nTimes","nXtrack")
+            END_OBJECT=GeoField_2
+        END_GROUP=GeoField
+        GROUP=DataField
+            OBJECT=DataField_1
+                DataFieldName="ColumnAmountO3"
+                DimList=("nTimes","nXtrack")
+            END_OBJECT=DataField_1
+            OBJECT=DataField_2
+                DataFieldName="Wavelength"
+                DimList=("nWavel")
+            END_OBJECT=DataField_2
+        END_GROUP=DataField
+    END_GROUP=SWATH_1
+END_GROUP=SwathStructure
+GROUP=GridStructure
+    GROUP=GRID_1
+        GridName="ColumnAmountO3"
+        XDim=360
+        YDim=180
+        UpperLeftPointMtrs=(-180000000.000000,90000000.000000)
+        LowerRightMtrs=(180000000.000000,-90000000.000000)
+        Projection=HE5_GCTP_GEO
+        GROUP=DataField
+            OBJECT=DataField_1
+                DataFieldName="ColumnAmountO3"
+                DimList=("YDim","XDim")
+            END_OBJECT=DataField_1
+        END_GROUP=DataField
+    END_GROUP=GRID_1
+END_GROUP=GridStructure
+"#;
+
+    #[test]
+    fn parse_swaths_and_grids() {
+        let metadata = StructMetadata::parse(STRUCT_METADATA);
+
+        assert_eq!(metadata.swaths.len(), 1);
+        assert_eq!(metadata.swaths[0].geo_fields.len(), 2);
+        assert_eq!(
+            metadata.swaths[0].data_fields[0].dimensions,
+            ["nTimes", "nXtrack"]
+        );
+
+        assert_eq!(metadata.grids.len(), 1);
+        assert_eq!(
+            (metadata.grids[0].columns, metadata.grids[0].rows),
+            (360, 180)
+        );
+        assert_eq!(metadata.grids[0].geometry(), ([1.0, -1.0], [-180.0, -90.0]));
+    }
+
+    #[test]
+    fn fields_are_timed_along_their_time_dimension() {
+        let metadata = StructMetadata::parse(STRUCT_METADATA);
+        let swath = "/HDFEOS/SWATHS/ColumnAmountO3";
+
+        assert_eq!(
+            metadata.time_of(&format!("{swath}/Data Fields/ColumnAmountO3")),
+            Some(format!("{swath}/Geolocation Fields/Time"))
+        );
+        assert_eq!(
+            metadata.time_of(&format!("{swath}/Data Fields/Wavelength")),
+            None
+        );
+
+        assert!(
+            metadata
+                .grid_of("/HDFEOS/GRIDS/ColumnAmountO3/Data Fields/ColumnAmountO3")
+                .is_some()
+        );
+        assert!(
+            metadata
+                .grid_of(&format!("{swath}/Data Fields/ColumnAmountO3"))
+                .is_none()
+        );
+
+        assert_eq!(
+            metadata.swath_times(),
+            [format!("{swath}/Geolocation Fields/Time")]
+        );
+    }
+
+    #[test]
+    fn range_beginning_from_core_metadata() {
+        let core_metadata = r#"
+GROUP=INVENTORYMETADATA
+    GROUP=RANGEDATETIME
+        OBJECT=RANGEBEGINNINGDATE
+            NUM_VAL=1
+            VALUE="2004-10-01"
+        END_OBJECT=RANGEBEGINNINGDATE
+        OBJECT=RANGEBEGINNINGTIME
+            NUM_VAL=1
+            VALUE="00:54:00.000000"
+        END_OBJECT=RANGEBEGINNINGTIME
+    END_GROUP=RANGEDATETIME
+END_GROUP=INVENTORYMETADATA
+"#;
+
+        assert_eq!(
+            range_beginning_of(core_metadata).as_deref(),
+            Some("2004-10-01T00:54:00.000000")
+        );
+        assert_eq!(range_beginning_of(STRUCT_METADATA), None);
+    }
+}
@@ -0,0 +1,654 @@
+use std::collections::BTreeMap;
+
+use anyhow::bail;
+use foxglove::Schema;
+use serde_json::{Map, Value, json};
+
+use crate::hdf5::{Attribute, Dataset, DatasetType};
+
+/// JSON schema for a single element of a dataset.
+fn element_schema(dataset: &Dataset) -> Value {
+    match dataset.type_ {
+        DatasetType::Integer => json!({ "type": "integer" }),
+        _ => json!({ "type": "number" }),
+    }
+}
+
+/// JSON schema for one row of a dataset, nesting arrays for each dimension after the first.
+pub fn row_schema(dataset: &Dataset) -> Value {
+    let mut schema = element_schema(dataset);
+
+    for dimension in dataset.dimensions.iter().skip(1).rev() {
+        schema = json!({
+            "type": "array",
+            "items": schema,
+            "minItems": dimension,
+            "maxItems": dimension,
+        });
+    }
+
+    schema
+}
+
+/// Read one row of a dataset as JSON, shaped like [`row_schema`].
+pub fn read_row(index: u64, dataset: &Dataset) -> anyhow::Result<Value> {
+    let values = read_values(index, dataset)?;
+
+    Ok(reshape(values, &dataset.dimensions[1..]))
+}
+
+/// Read the elements of one row of a dataset as flat JSON values.
+fn read_values(index: u64, dataset: &Dataset) -> anyhow::Result<Vec<Value>> {
+    let values = match dataset.type_ {
+        DatasetType::Integer if dataset.signed => dataset
+            .read_at_index::<i64>(index)?
+            .into_iter()
+            .map(Value::from)
+            .collect(),
+        DatasetType::Integer => dataset
+            .read_at_index::<u64>(index)?
+            .into_iter()
+            .map(Value::from)
+            .collect(),
+        DatasetType::Float => dataset
+            .read_at_index::<f64>(index)?
+            .into_iter()
+            .map(Value::from)
+            .collect(),
+        t => bail!("unsupported dataset type {t:?} for json"),
+    };
+
+    Ok(values)
+}
+
+fn reshape(mut values: Vec<Value>, shape: &[u64]) -> Value {
+    let Some((_, inner)) = shape.split_first() else {
+        return values.pop().unwrap_or(Value::Null);
+    };
+
+    let size = inner.iter().product::<u64>().max(1) as usize;
+
+    let mut rows = vec![];
+
+    while !values.is_empty() {
+        let rest = values.split_off(size.min(values.len()));
+        rows.push(reshape(values, inner));
+        values = rest;
+    }
+
+    Value::Array(rows)
+}
+
+/// Wrap a set of properties as an object schema, registered under `name`.
+pub fn object_schema(name: &str, properties: Map<String, Value>) -> anyhow::Result<Schema> {
+    let required: Vec<&String> = properties.keys().collect();
+
+    let schema = json!({
+        "type": "object",
+        "title": name,
+        "properties": properties,
+        "required": required,
+    });
+
+    Ok(Schema::new(
+        name,
+        "jsonschema",
+        serde_json::to_vec(&schema)?,
+    ))
+}
+
+/// Datasets of one group that share a time index, published together as one message per row
+/// with a field for each dataset.
+#[derive(Debug, Clone)]
+pub struct MergedGroup {
+    pub name: String,
+    pub fields: Vec<(String, Dataset)>,
+}
+
+impl MergedGroup {
+    pub fn schema(&self) -> anyhow::Result<Schema> {
+        let properties = self
+            .fields
+            .iter()
+            .map(|(name, dataset)| (name.clone(), row_schema(dataset)))
+            .collect();
+
+        object_schema(&self.name, properties)
+    }
+}
+
+pub fn serialize_merged_group(index: u64, group: &MergedGroup) -> anyhow::Result<Vec<u8>> {
+    let mut message = Map::new();
+
+    for (name, dataset) in group.fields.iter() {
+        message.insert(name.clone(), read_row(index, dataset)?);
+    }
+
+    Ok(serde_json::to_vec(&message)?)
+}
+
+/// Names for the columns of a `(T, N)` dataset, published as one field per column. Scalar
+/// datasets are published as a single `value` field.
+#[derive(Debug, Clone)]
+pub struct NamedColumns {
+    pub columns: Vec<String>,
+}
+
+/// Field names for small fixed size rows, such as positions or quaternions.
+const COMPONENT_NAMES: [&str; 4] = ["x", "y", "z", "w"];
+
+impl NamedColumns {
+    /// Column names come from a `column_names` or `joint_names` attribute, a dimension scale of
+    /// strings attached to the columns, or a pandas style `{prefix}_items` dataset next to a
+    /// `{prefix}_values` dataset. Rows of up to four values without names use `x`, `y`, `z` and
+    /// `w`.
+    pub fn from_dataset(dataset: &Dataset, datasets: &BTreeMap<String, Dataset>) -> Option<Self> {
+        if !matches!(dataset.type_, DatasetType::Integer | DatasetType::Float) {
+            return None;
+        }
+
+        let count = match dataset.dimensions[..] {
+            [_] => {
+                return Some(Self {
+                    columns: vec!["value".to_string()],
+                });
+            }
+            [_, count] => count,
+            _ => return None,
+        };
+
+        let is_vector = count as usize <= COMPONENT_NAMES.len()
+            && !dataset.is_audio_topic()
+            && !dataset.is_laser_scan_topic();
+
+        let columns = ["column_names", "joint_names"]
+            .iter()
+            .find_map(|name| dataset.attrs.get(*name).and_then(Attribute::as_strings))
+            .or_else(|| {
+                let axis = *dataset.row_axes().last()?;
+                let scale = dataset.dimension_scales(datasets).get(axis)?.scale?;
+
+                matches!(scale.type_, DatasetType::String)
+                    .then(|| scale.read_strings().ok())
+                    .flatten()
+            })
+            .or_else(|| {
+                let prefix = dataset.name.strip_suffix("_values")?;
+                datasets
+                    .get(&format!("{prefix}_items"))?
+                    .read_strings()
+                    .ok()
+            })
+            .or_else(|| {
+                is_vector.then(|| {
+                    COMPONENT_NAMES[..count as usize]
+                        .iter()
+                        .map(|x| x.to_string())
+                        .collect()
+                })
+            })?;
+
+        if columns.len() as u64 != count {
+            return None;
+        }
+
+        let columns = columns
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| {
+                if name.is_empty() {
+                    format!("column_{i}")
+                } else {
+                    name
+                }
+            })
+            .collect();
+
+        Some(Self { columns })
+    }
+
+    pub fn schema(&self, dataset: &Dataset) -> anyhow::Result<Schema> {
+        let properties = self
+            .columns
+            .iter()
+            .map(|name| (name.clone(), element_schema(dataset)))
+            .collect();
+
+        object_schema(&dataset.name, properties)
+    }
+}
+
+pub fn serialize_named_columns(
+    index: u64,
+    dataset: &Dataset,
+    columns: &NamedColumns,
+) -> anyhow::Result<Vec<u8>> {
+    let values = read_values(index, dataset)?;
+
+    let message: Map<String, Value> = columns.columns.iter().cloned().zip(values).collect();
+
+    Ok(serde_json::to_vec(&message)?)
+}
+
+/// A pandas DataFrame reassembled from its blocks of columns, published as one message per row
+/// with a field for each column.
+#[derive(Debug, Clone)]
+pub struct DataFrame {
+    pub name: String,
+    /// The index of the DataFrame, the timestamps of its rows.
+    pub index: Dataset,
+    /// Whether the index holds datetimes, in nanoseconds since the unix epoch.
+    pub datetime_index: bool,
+    pub blocks: Vec<(NamedColumns, Dataset)>,
+}
+
+impl DataFrame {
+    /// Reassemble the DataFrame stored in a group by pandas' `HDFStore`. The fixed format stores
+    /// the index as `axis1` and the columns of each type as `block{N}_values` named by
+    /// `block{N}_items`. The table format stores a PyTables `table` whose members are the
+    /// index, the `values_block_{N}` columns and the data columns.
+    pub fn from_group(group: &str, datasets: &BTreeMap<String, Dataset>) -> Option<Self> {
+        match datasets.get(&format!("{group}/table")) {
+            Some(table) => Self::from_table(group, table, datasets),
+            None => Self::from_blocks(group, datasets),
+        }
+    }
+
+    fn from_blocks(group: &str, datasets: &BTreeMap<String, Dataset>) -> Option<Self> {
+        let index = datasets.get(&format!("{group}/axis1"))?;
+
+        let blocks = (0..)
+            .map_while(|i| {
+                let values = datasets.get(&format!("{group}/block{i}_values"))?;
+                let items = datasets.get(&format!("{group}/block{i}_items"));
+                Some((values, items))
+            })
+            .filter_map(|(values, items)| {
+                let [_, count] = values.dimensions[..] else {
+                    return None;
+                };
+
+                let names = items.and_then(|x| {
+                    x.read_strings().ok().or_else(|| {
+                        Some(x.read::<i64>().ok()?.0.iter().map(i64::to_string).collect())
+                    })
+                });
+
+                Some((column_names(names, count, &values.name), values.clone()))
+            })
+            .filter(|(_, values)| matches!(values.type_, DatasetType::Integer | DatasetType::Float))
+            .collect();
+
+        Self::new(group, index, attr_str(index, "kind"), blocks)
+    }
+
+    fn from_table(
+        group: &str,
+        table: &Dataset,
+        datasets: &BTreeMap<String, Dataset>,
+    ) -> Option<Self> {
+        let index = datasets.get(&format!("{}/index", table.name))?;
+
+        let blocks = datasets
+            .range(format!("{}/", table.name)..)
+            .take_while(|(name, _)| name.starts_with(&format!("{}/", table.name)))
+            .map(|(_, x)| x)
+            .filter(|x| x.name != index.name && x.member.is_some())
+            .filter_map(|values| {
+                let (_, member) = values.name.rsplit_once('/')?;
+
+                // data columns are members of their own, named after the column
+                let count = match values.dimensions[..] {
+                    [_] => {
+                        return Some((
+                            NamedColumns {
+                                columns: vec![member.to_string()],
+                            },
+                            values.clone(),
+                        ));
+                    }
+                    [_, count] => count,
+                    _ => return None,
+                };
+
+                // the column names of a block are pickled by PyTables
+                let names = table
+                    .read_attr_bytes(&format!("{member}_kind"))
+                    .and_then(|x| pickled_strings(&x));
+
+                Some((column_names(names, count, &values.name), values.clone()))
+            })
+            .collect();
+
+        Self::new(group, index, attr_str(table, "index_kind"), blocks)
+    }
+
+    fn new(
+        group: &str,
+        index: &Dataset,
+        index_kind: Option<&str>,
+        blocks: Vec<(NamedColumns, Dataset)>,
+    ) -> Option<Self> {
+        if blocks.is_empty()
+            || index.dimensions.len() != 1
+            || !matches!(index.type_, DatasetType::Integer | DatasetType::Float)
+        {
+            return None;
+        }
+
+        Some(Self {
+            name: group.to_string(),
+            index: index.clone(),
+            datetime_index: index_kind.is_some_and(|x| x.starts_with("datetime64")),
+            blocks,
+        })
+    }
+
+    pub fn schema(&self) -> anyhow::Result<Schema> {
+        let properties = self
+            .blocks
+            .iter()
+            .flat_map(|(columns, dataset)| {
+                columns
+                    .columns
+                    .iter()
+                    .map(|name| (name.clone(), element_schema(dataset)))
+            })
+            .collect();
+
+        object_schema(&self.name, properties)
+    }
+}
+
+pub fn serialize_data_frame(index: u64, frame: &DataFrame) -> anyhow::Result<Vec<u8>> {
+    let mut message = Map::new();
+
+    for (columns, dataset) in frame.blocks.iter() {
+        let values = read_values(index, dataset)?;
+        message.extend(columns.columns.iter().cloned().zip(values));
+    }
+
+    Ok(serde_json::to_vec(&message)?)
+}
+
+fn attr_str<'a>(dataset: &'a Dataset, name: &str) -> Option<&'a str> {
+    dataset.attrs.get(name).and_then(Attribute::as_str)
+}
+
+/// Names for the `count` columns of a block, `{block}_{N}` when they're missing.
+fn column_names(names: Option<Vec<String>>, count: u64, block: &str) -> NamedColumns {
+    let block = block.rsplit_once('/').map(|(_, x)| x).unwrap_or(block);
+
+    let columns = match names {
+        Some(names) if names.len() as u64 == count => names,
+        _ => (0..count).map(|i| format!("{block}_{i}")).collect(),
+    };
+
+    NamedColumns { columns }
+}
+
+/// Values of a pickle stream, as far as lists of strings need them.
+#[derive(Debug, Clone)]
+enum Pickled {
+    Str(String),
+    List(Vec<Pickled>),
+    Tuple(Vec<Pickled>),
+    Mark,
+}
+
+/// The strings of a pickled list or tuple of strings, as written by protocols 2 to 5. Streams
+/// with other opcodes, like the numpy objects of some column names, aren't read.
+fn pickled_strings(bytes: &[u8]) -> Option<Vec<String>> {
+    let mut stack: Vec<Pickled> = vec![];
+    let mut memo: BTreeMap<u64, Pickled> = BTreeMap::new();
+    let mut i = 0;
+
+    let take = |i: &mut usize, len: usize| {
+        let value = bytes.get(*i..*i + len)?;
+        *i += len;
+        Some(value)
+    };
+
+    let uint = |value: &[u8]| {
+        let mut buffer = [0; 8];
+        buffer[..value.len()].copy_from_slice(value);
+        u64::from_le_bytes(buffer)
+    };
+
+    let since_mark = |stack: &mut Vec<Pickled>| {
+        let mark = stack.iter().rposition(|x| matches!(x, Pickled::Mark))?;
+        let items = stack.split_off(mark + 1);
+        stack.pop();
+        Some(items)
+    };
+
+    loop {
+        let opcode = *take(&mut i, 1)?.first()?;
+
+        match opcode {
+            // PROTO and FRAME
+            0x80 => i += 1,
+            0x95 => i += 8,
+            // SHORT_BINUNICODE, BINUNICODE and BINUNICODE8
+            0x8c | b'X' | 0x8d => {
+                let size = match opcode {
+                    0x8c => 1,
+                    b'X' => 4,
+                    _ => 8,
+                };
+
+                let len = uint(take(&mut i, size)?) as usize;
+                let value = String::from_utf8(take(&mut i, len)?.to_vec()).ok()?;
+                stack.push(Pickled::Str(value));
+            }
+            b']' => stack.push(Pickled::List(vec![])),
+            b')' => stack.push(Pickled::Tuple(vec![])),
+            b'(' => stack.push(Pickled::Mark),
+            // APPEND and APPENDS
+            b'a' | b'e' => {
+                let items = match opcode {
+                    b'a' => vec![stack.pop()?],
+                    _ => since_mark(&mut stack)?,
+                };
+
+                let Some(Pickled::List(list)) = stack.last_mut() else {
+                    return None;
+                };
+
+                list.extend(items);
+            }
+            // TUPLE, TUPLE1, TUPLE2 and TUPLE3
+            b't' => {
+                let items = since_mark(&mut stack)?;
+                stack.push(Pickled::Tuple(items));
+            }
+            0x85..=0x87 => {
+                let len = (opcode - 0x84) as usize;
+                let items = stack.split_off(stack.len().checked_sub(len)?);
+                stack.push(Pickled::Tuple(items));
+            }
+            // BINPUT, LONG_BINPUT and MEMOIZE
+            b'q' | b'r' | 0x94 => {
+                let key = match opcode {
+                    b'q' => uint(take(&mut i, 1)?),
+                    b'r' => uint(take(&mut i, 4)?),
+                    _ => memo.len() as u64,
+                };
+
+                memo.insert(key, stack.last()?.clone());
+            }
+            // BINGET and LONG_BINGET
+            b'h' | b'j' => {
+                let key = uint(take(&mut i, if opcode == b'h' { 1 } else { 4 })?);
+                stack.push(memo.get(&key)?.clone());
+            }
+            // STOP
+            b'.' => break,
+            _ => return None,
+        }
+    }
+
+    let (Some(Pickled::List(items)) | Some(Pickled::Tuple(items))) = stack.pop() else {
+        return None;
+    };
+
+    items
+        .into_iter()
+        .map(|x| match x {
+            Pickled::Str(x) => Some(x),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Coordinate values along the axes of a row, from the numeric dimension scales attached to
+/// them, keyed by the name of the axis.
+pub fn coordinates(dataset: &Dataset, datasets: &BTreeMap<String, Dataset>) -> Map<String, Value> {
+    let mut out = Map::new();
+
+    // the axes of column major datasets are reordered, and they have no dimension scales
+    if dataset.column_major {
+        return out;
+    }
+
+    let dimensions = dataset.dimension_scales(datasets);
+
+    for axis in dataset.row_axes() {
+        let Some(dimension) = dimensions.get(axis) else {
+            continue;
+        };
+
+        let (Some(name), Some(scale)) = (dimension.name(), dimension.scale) else {
+            continue;
+        };
+
+        if !matches!(scale.type_, DatasetType::Integer | DatasetType::Float) {
+            continue;
+        }
+
+        if let Ok((values, _)) = scale.read::<f64>() {
+            out.insert(name, values.into());
+        }
+    }
+
+    out
+}
+
+/// Schema for a generic dataset published as JSON. This mirrors the fields of the protobuf
+/// `RawIntegerDataset` and `RawFloatDataset`, with `dataset` shaped by the dataset's dimensions,
+/// plus the `coordinates` of its axes.
+pub fn dataset_schema(
+    dataset: &Dataset,
+    coordinates: &Map<String, Value>,
+) -> anyhow::Result<Schema> {
+    let mut properties = Map::new();
+
+    properties.insert(
+        "dimensions".to_string(),
+        json!({ "type": "array", "items": { "type": "integer" } }),
+    );
+    properties.insert("dataset".to_string(), row_schema(dataset));
+
+    if !coordinates.is_empty() {
+        properties.insert("coordinates".to_string(), axes_schema(coordinates));
+    }
+
+    object_schema(&dataset.name, properties)
+}
+
+fn axes_schema(coordinates: &Map<String, Value>) -> Value {
+    let axes = coordinates
+        .keys()
+        .map(|name| {
+            (
+                name.clone(),
+                json!({ "type": "array", "items": { "type": "number" } }),
+            )
+        })
+        .collect();
+
+    json!({ "type": "object", "properties": Value::Object(axes) })
+}
+
+/// Schema for the coordinates of a dataset's axes published on a topic of their own.
+pub fn coordinates_schema(
+    dataset: &Dataset,
+    coordinates: &Map<String, Value>,
+) -> anyhow::Result<Schema> {
+    let mut properties = Map::new();
+    properties.insert("coordinates".to_string(), axes_schema(coordinates));
+
+    object_schema(&format!("{}/coordinates", dataset.name), properties)
+}
+
+pub fn serialize_coordinates(coordinates: &Map<String, Value>) -> anyhow::Result<Vec<u8>> {
+    Ok(serde_json::to_vec(&json!({ "coordinates": coordinates }))?)
+}
+
+pub fn serialize_dataset(
+    index: u64,
+    dataset: &Dataset,
+    coordinates: &Map<String, Value>,
+) -> anyhow::Result<Vec<u8>> {
+    let mut message = json!({
+        "dimensions": dataset.dimensions[1..],
+        "dataset": read_row(index, dataset)?,
+    });
+
+    if !coordinates.is_empty() {
+        message["coordinates"] = Value::Object(coordinates.clone());
+    }
+
+    Ok(serde_json::to_vec(&message)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reshape_nests_rows() {
+        let values = |n: i64| (0..n).map(Value::from).collect::<Vec<_>>();
+
+        assert_eq!(reshape(values(1), &[]), json!(0));
+        assert_eq!(reshape(values(3), &[3]), json!([0, 1, 2]));
+        assert_eq!(reshape(values(6), &[2, 3]), json!([[0, 1, 2], [3, 4, 5]]));
+        assert_eq!(
+            reshape(values(8), &[2, 2, 2]),
+            json!([[[0, 1], [2, 3]], [[4, 5], [6, 7]]])
+        );
+        assert_eq!(reshape(vec![], &[]), Value::Null);
+    }
+
+    #[test]
+    fn pickled_lists_of_strings() {
+        let strings = Some(vec!["a".to_string(), "bc".to_string()]);
+
+        // pickle.dumps(['a', 'bc'], protocol=2)
+        let protocol_2 = b"\x80\x02]q\x00(X\x01\x00\x00\x00aq\x01X\x02\x00\x00\x00bcq\x02e.";
+        assert_eq!(pickled_strings(protocol_2), strings);
+
+        // pickle.dumps(['a', 'bc'], protocol=4)
+        let protocol_4 =
+            b"\x80\x04\x95\x0e\x00\x00\x00\x00\x00\x00\x00]\x94(\x8c\x01a\x94\x8c\x02bc\x94e.";
+        assert_eq!(pickled_strings(protocol_4), strings);
+
+        // pickle.dumps(['a', 'bc'], protocol=5)
+        let protocol_5 =
+            b"\x80\x05\x95\x0e\x00\x00\x00\x00\x00\x00\x00]\x94(\x8c\x01a\x94\x8c\x02bc\x94e.";
+        assert_eq!(pickled_strings(protocol_5), strings);
+
+        // pickle.dumps(('a', 'bc'), protocol=2)
+        let tuple = b"\x80\x02X\x01\x00\x00\x00aq\x00X\x02\x00\x00\x00bcq\x01\x86q\x02.";
+        assert_eq!(pickled_strings(tuple), strings);
+    }
+
+    #[test]
+    fn other_pickles_are_not_strings() {
+        // pickle.dumps(['a', 1], protocol=2)
+        let integers = b"\x80\x02]q\x00(X\x01\x00\x00\x00aq\x01K\x01e.";
+        assert_eq!(pickled_strings(integers), None);
+
+        assert_eq!(pickled_strings(b"\x80\x02]q\x00(X\x05\x00"), None);
+    }
+}
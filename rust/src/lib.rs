@@ -1,23 +1,33 @@
 mod dlopen_stub;
 
+pub mod converter;
+pub mod flavour;
 pub mod hdf5;
+pub mod hdf_eos;
+pub mod json;
 pub mod log;
+pub mod mapping;
 pub mod messages;
 pub mod wasm_vfs;
 
-use std::{borrow::Cow, collections::BTreeMap};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    rc::Rc,
+};
 
-use foxglove::{Encode, Schema, schemas::RawImage};
+use converter::{Plan, Registry, TopicOptions, json_plan};
+use flavour::{Flavour, Sampling, TimeBase, time_scale};
 use hdf5::*;
-use messages::{
-    RawFloatDataset, RawIntegerDataset, serialize_float_raw, serialize_integer_raw,
-    serialize_mono16_raw_image, serialize_rgb8_raw_image,
-};
+use json::{DataFrame, MergedGroup, serialize_data_frame, serialize_merged_group};
+use mapping::{Mapping, PlaybackTime, Rule, TimeScale, TimeUnit};
+use messages::MessageEncoding;
 use smallvec::SmallVec;
 
 use foxglove_data_loader::{
-    BackfillArgs, DataLoader, Initialization, Message, MessageIterator, MessageIteratorArgs,
-    Problem, console,
+    BackfillArgs, DataLoader, Initialization, InitializationBuilder, Message, MessageIterator,
+    MessageIteratorArgs, Problem, console,
 };
 
 struct Hdf5Iterator {
@@ -98,15 +108,257 @@ impl MessageIterator for Hdf5Iterator {
 
 type TimestampIndex = BTreeMap<u64, SmallVec<[u64; 4]>>;
 
-trait SerializeMessage: std::fmt::Debug {
-    fn to_message(&self, index: u64, dataset: &Dataset) -> Vec<u8>;
+/// The dataset holding the timestamps of every dataset in a group.
+fn group_timestamp<'a>(
+    flavour: &Flavour,
+    datasets: &'a BTreeMap<String, Dataset>,
+    group: &str,
+) -> Option<&'a Dataset> {
+    flavour
+        .group_timestamp_names()
+        .iter()
+        .find_map(|name| datasets.get(&format!("{group}/{name}")))
+}
+
+/// Companions of a dataset holding its timestamps on other clocks than `timestamp`, like the
+/// hardware clock of a sensor or GPS time.
+const CLOCK_NAMES: [&str; 3] = ["hw_timestamp", "gps_time", "receive_time"];
+
+/// Companions of a dataset holding when its messages were published, like the stamps of their
+/// headers.
+const PUBLISH_TIME_NAMES: [&str; 2] = ["header_stamp", "publish_time"];
+
+/// The group whose timestamps time a dataset. The elements of MATLAB cell arrays are named
+/// `{cell}/{index}`, so they are timed by the struct holding the cell array.
+fn timing_group<'a>(datasets: &BTreeMap<String, Dataset>, name: &'a str) -> Option<&'a str> {
+    let (group, _) = name.rsplit_once('/')?;
+
+    match datasets.contains_key(group) {
+        true => group.rsplit_once('/').map(|(group, _)| group),
+        false => Some(group),
+    }
+}
+
+/// The first of the companions named `names` of a dataset, a `{name}.{x}` dataset or an `{x}`
+/// dataset in its group.
+fn time_companion<'a>(
+    datasets: &'a BTreeMap<String, Dataset>,
+    name: &str,
+    names: &[&str],
+) -> Option<&'a Dataset> {
+    let group = timing_group(datasets, name)?;
+
+    names.iter().find_map(|x| {
+        datasets
+            .get(&format!("{name}.{x}"))
+            .or_else(|| datasets.get(&format!("{group}/{x}")))
+    })
+}
+
+/// A dataset holding the timestamps of another on one of the clocks of the file.
+struct Clock<'a> {
+    name: String,
+    dataset: &'a Dataset,
+    /// Axis of the timed dataset indexed by the timestamps, for dimension scales.
+    time_axis: Option<usize>,
+}
+
+/// The clocks a dataset can be timed by, in the default priority: its time dimension scale, its
+/// `timestamp`, the companions of [`CLOCK_NAMES`] and then the companions in `names`.
+fn clocks<'a>(
+    flavour: &Flavour,
+    datasets: &'a BTreeMap<String, Dataset>,
+    dataset: &Dataset,
+    names: &[String],
+) -> Vec<Clock<'a>> {
+    let mut out = vec![];
+
+    if let Some((axis, x)) = flavour.time_coordinate(dataset, datasets) {
+        out.push(Clock {
+            name: x.name.rsplit('/').next().unwrap_or_default().to_string(),
+            dataset: x,
+            time_axis: Some(axis),
+        });
+    }
+
+    // a `timestamp` dataset specific to this dataset, or one shared by the whole group
+    let timestamp = datasets
+        .get(&format!("{}.timestamp", dataset.name))
+        .or_else(|| {
+            let group = timing_group(datasets, &dataset.name)?;
+            group_timestamp(flavour, datasets, group)
+        });
+
+    if let Some(x) = timestamp {
+        out.push(Clock {
+            name: "timestamp".to_string(),
+            dataset: x,
+            time_axis: None,
+        });
+    }
+
+    let names = CLOCK_NAMES
+        .iter()
+        .copied()
+        .chain(names.iter().map(String::as_str));
+
+    for name in names {
+        let Some(x) = time_companion(datasets, &dataset.name, &[name]) else {
+            continue;
+        };
+
+        if out.iter().any(|clock| clock.dataset.name == x.name) {
+            continue;
+        }
+
+        out.push(Clock {
+            name: name.to_string(),
+            dataset: x,
+            time_axis: None,
+        });
+    }
+
+    out
+}
+
+/// Whether a dataset is the timestamps of others on another clock, including the `clocks` named
+/// by mapping rules, or their publish times.
+fn is_time_companion(basename: &str, clocks: &[&str]) -> bool {
+    CLOCK_NAMES
+        .iter()
+        .chain(PUBLISH_TIME_NAMES.iter())
+        .chain(clocks.iter())
+        .any(|x| basename == *x || basename.ends_with(&format!(".{x}")))
+}
+
+/// Read a timestamp dataset in nanoseconds since the unix epoch.
+fn read_timestamps(timestamp_dataset: &Dataset, base: TimeBase) -> anyhow::Result<Vec<u64>> {
+    if !matches!(
+        timestamp_dataset.type_,
+        DatasetType::Integer | DatasetType::Float
+    ) {
+        anyhow::bail!(
+            "timestamps need an integer or float dataset, not {:?}",
+            timestamp_dataset.type_
+        );
+    }
+
+    // GPS receivers record pairs of week number and seconds into the week
+    if base.scale == TimeScale::Gps && matches!(timestamp_dataset.stored_dimensions[..], [_, 2]) {
+        let (timestamp_data, _) = timestamp_dataset.read::<f64>()?;
+
+        return Ok(timestamp_data
+            .chunks_exact(2)
+            .map(|x| TimeBase::gps_week_timestamp(x[0], x[1]))
+            .collect());
+    }
+
+    // fractional timestamps are kept when converting to nanoseconds
+    let timestamp_data: Vec<u64> = match timestamp_dataset.type_ {
+        DatasetType::Float => {
+            let (timestamp_data, _) = timestamp_dataset.read::<f64>()?;
+            timestamp_data
+                .into_iter()
+                .map(|x| base.timestamp_f64(x))
+                .collect()
+        }
+        _ => {
+            let (timestamp_data, _) = timestamp_dataset.read::<u64>()?;
+            timestamp_data
+                .into_iter()
+                .map(|x| base.timestamp_u64(x))
+                .collect()
+        }
+    };
+
+    Ok(timestamp_data)
+}
+
+/// The time base of a timestamp dataset, from the unit and time scale of the rule matching the
+/// timed dataset, or from the flavour.
+fn rule_time_base(flavour: &Flavour, rule: &Rule, timestamp_dataset: &Dataset) -> TimeBase {
+    let scale = rule.time_scale.or_else(|| time_scale(timestamp_dataset));
+
+    match (rule.unit, scale) {
+        (None, _) => flavour.time_base(timestamp_dataset, scale),
+        (Some(unit), Some(scale)) => TimeBase::from(unit).on_scale(scale),
+        (Some(unit), None) => unit.into(),
+    }
+}
+
+/// Index rows by their timestamps, returning the index and the number of rows.
+fn index_timestamps(timestamp_data: Vec<u64>) -> (TimestampIndex, u64) {
+    let mut timestamps: TimestampIndex = Default::default();
+    let mut message_count = 0;
+
+    for (i, timestamp) in timestamp_data.into_iter().enumerate() {
+        let entry = timestamps.entry(timestamp).or_default();
+        entry.push(i as u64);
+        message_count += 1;
+    }
+
+    (timestamps, message_count)
+}
+
+/// Timestamps of `rows` rows recorded at a fixed rate.
+fn sampled_times(sampling: Sampling, rows: u64) -> Vec<u64> {
+    (0..rows)
+        .map(|i| (sampling.start + (i as f64 / sampling.rate * 1e9) as i64).max(0) as u64)
+        .collect()
+}
+
+/// Timestamps of the rows of a topic, an error naming both datasets when they can't be read.
+fn topic_timestamps(
+    name: &str,
+    timestamp_dataset: &Dataset,
+    base: TimeBase,
+) -> Result<Vec<u64>, Problem> {
+    read_timestamps(timestamp_dataset, base).map_err(|e| {
+        Problem::warn(format!("Invalid timestamps for {name}"))
+            .tip(format!("Failed to read {}: {e:#}", timestamp_dataset.name))
+    })
+}
+
+/// The problem reported for a topic which can't be published.
+fn publish_problem(name: &str, e: anyhow::Error) -> Problem {
+    Problem::warn(format!("Cannot publish {name}")).tip(format!("{e:#}"))
+}
+
+/// Publish times read for the rows of a topic, an error when they don't match its timestamps.
+fn matching_publish_times(
+    name: &str,
+    rows: usize,
+    publish_times: Option<Vec<u64>>,
+) -> Result<Option<Vec<u64>>, Problem> {
+    match publish_times {
+        Some(x) if x.len() != rows => Err(Problem::warn(format!(
+            "Publish timestamps of {name} don't match its timestamps"
+        ))
+        .tip("The publish timestamp dataset needs one value per timestamp.")),
+        x => Ok(x),
+    }
+}
+
+/// The log and publish times of the rows of a topic, swapped when messages play back in the
+/// order of their publish time.
+fn playback_times(
+    log_times: Vec<u64>,
+    publish_times: Option<Vec<u64>>,
+    playback: PlaybackTime,
+) -> (Vec<u64>, Option<Vec<u64>>) {
+    match (playback, publish_times) {
+        (PlaybackTime::PublishTime, Some(publish_times)) => (publish_times, Some(log_times)),
+        (_, publish_times) => (log_times, publish_times),
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 struct Topic {
     dataset: Dataset,
     timestamps: TimestampIndex,
-    serialize_message: fn(u64, &Dataset) -> anyhow::Result<Vec<u8>>,
+    /// The publish time of each row, when it differs from the log time in `timestamps`.
+    publish_times: Option<Vec<u64>>,
+    plan: Rc<dyn Plan>,
 }
 
 impl Topic {
@@ -120,11 +372,18 @@ impl Topic {
         let mut out = SmallVec::<[Message; 4]>::new();
 
         for index in indexes {
-            let data = handle!((self.serialize_message)(*index, &self.dataset));
+            let data = handle!(self.plan.serialize(*index, &self.dataset));
+
+            let publish_time = self
+                .publish_times
+                .as_ref()
+                .and_then(|x| x.get(*index as usize).copied())
+                .unwrap_or(timestamp);
+
             out.push(Message {
                 channel_id,
                 log_time: timestamp,
-                publish_time: timestamp,
+                publish_time,
                 data,
             });
         }
@@ -133,161 +392,806 @@ impl Topic {
     }
 }
 
+/// Datasets of a group published as one topic, with the timing they share.
+struct PendingGroup {
+    timestamp: Dataset,
+    clock: String,
+    publish_timestamp: Option<String>,
+    timestamps: TimestampIndex,
+    message_count: u64,
+    publish_times: Option<Vec<u64>>,
+    fields: Vec<(String, Dataset)>,
+}
+
+/// Topic prefixes telling the opened files apart: their stem, or their path without extension
+/// when several files have the same stem, and their position when even those are the same.
+fn file_prefixes<'a>(paths: impl Iterator<Item = &'a str> + Clone) -> Vec<String> {
+    let stem = |path: &str| match Path::new(path).file_stem() {
+        Some(stem) => format!("/{}", stem.to_string_lossy()),
+        None => path.to_string(),
+    };
+
+    let stem_path = |path: &str| {
+        let path = Path::new(path).with_extension("");
+        format!("/{}", path.to_string_lossy().trim_start_matches('/'))
+    };
+
+    let count =
+        |prefixes: &[String], prefix: &String| prefixes.iter().filter(|x| *x == prefix).count();
+
+    let stems: Vec<String> = paths.clone().map(stem).collect();
+
+    let prefixes: Vec<String> = paths
+        .zip(stems.iter())
+        .map(|(path, x)| match count(&stems, x) {
+            1 => x.clone(),
+            _ => stem_path(path),
+        })
+        .collect();
+
+    prefixes
+        .iter()
+        .enumerate()
+        .map(|(i, x)| match count(&prefixes, x) {
+            1 => x.clone(),
+            _ => format!("{x}_{i}"),
+        })
+        .collect()
+}
+
 struct Hdf5Loader {
-    path: String,
-    file: Option<Hdf5File>,
+    paths: Vec<String>,
+    mapping_paths: Vec<String>,
+    mapping: Mapping,
+    converters: Registry,
+    files: Vec<Hdf5File>,
     topics: Vec<Topic>,
+    /// Topics left out for lack of channel ids.
+    dropped_topics: usize,
 }
 
-impl DataLoader for Hdf5Loader {
-    type MessageIterator = Hdf5Iterator;
-    type Error = anyhow::Error;
+impl Hdf5Loader {
+    /// Add the channel of a topic. Channel ids are 16 bits, so topics past the last id are only
+    /// counted in `dropped_topics`.
+    fn add_topic(
+        &mut self,
+        init: &mut InitializationBuilder,
+        name: &str,
+        message_count: u64,
+        topic: Topic,
+    ) -> anyhow::Result<()> {
+        let Ok(id) = u16::try_from(self.topics.len()) else {
+            self.dropped_topics += 1;
+            return Ok(());
+        };
+
+        topic
+            .plan
+            .add_schema(init)?
+            .add_channel_with_id(id, name)
+            .expect("not in use")
+            .message_count(message_count);
+
+        self.topics.push(topic);
+
+        Ok(())
+    }
 
-    fn new(args: foxglove_data_loader::DataLoaderArgs) -> Self {
-        let path = args.paths.first().unwrap();
-        Self {
-            path: path.clone(),
-            file: None,
-            topics: vec![],
+    /// Add the topics of one file. Topics of datasets in `shared`, which exist in several of the
+    /// opened files, are prefixed with `prefix`. Topics which can't be published are reported as
+    /// problems, so the other topics and files are still published.
+    fn add_file(
+        &mut self,
+        mut init: InitializationBuilder,
+        path: &str,
+        prefix: &str,
+        file: &Hdf5File,
+        datasets: &BTreeMap<String, Dataset>,
+        shared: &BTreeSet<String>,
+    ) -> InitializationBuilder {
+        let root_attrs = file.get_attrs();
+        let flavour = Flavour::detect(path, file, &root_attrs, datasets);
+
+        if let Flavour::HdfEos {
+            metadata,
+            start: None,
+        } = &flavour
+            && !metadata.grids.is_empty()
+        {
+            init = init.add_problem(
+                Problem::warn(format!("Missing granule time of {path}"))
+                    .tip("Grids are placed at the start of the granule, from the GranuleYear, GranuleMonth and GranuleDay or TAI93At0zOfGranule file attributes, the RANGEBEGINNINGDATE of the core metadata or the Time of a swath."),
+            );
         }
-    }
 
-    fn initialize(&mut self) -> Result<foxglove_data_loader::Initialization, Self::Error> {
-        console::log("creating loader");
+        let mut datasets = datasets.clone();
+        datasets.extend(flavour.linked_datasets(&datasets));
+        let datasets = &datasets;
+
+        let mut mapping = flavour.rules(file, datasets);
+
+        // rules embedded by the writer of the file follow the flavour's conventions, so a
+        // mapping file opened alongside it can still override them
+        if let Some(contents) = root_attrs
+            .get("foxglove_mapping")
+            .and_then(Attribute::as_str)
+        {
+            match Mapping::parse("foxglove_mapping.json", contents) {
+                Ok(embedded) => mapping.extend(embedded),
+                Err(e) => {
+                    init = init.add_problem(
+                        Problem::error(format!("Invalid foxglove_mapping attribute in {path}"))
+                            .tip(format!("The attribute must be a JSON mapping: {e:#}")),
+                    );
+                }
+            }
+        }
 
-        init_lzf();
+        mapping.extend(self.mapping.clone());
 
-        let file = self.path.clone();
+        // generic datasets are encoded as protobuf unless the file or dataset asks for json
+        let mut default_encoding = None;
 
-        let mut init = Initialization::builder();
+        if let Some(encoding) = root_attrs
+            .get("foxglove_encoding")
+            .and_then(Attribute::as_str)
+        {
+            match encoding.parse() {
+                Ok(encoding) => default_encoding = Some(encoding),
+                Err(e) => {
+                    init = init.add_problem(
+                        Problem::warn(format!("Invalid foxglove_encoding attribute: {e}"))
+                            .tip("Supported encodings are \"protobuf\" and \"json\"."),
+                    );
+                }
+            }
+        }
 
-        console::error("opening a file");
+        let mapping_clocks: Vec<&str> = mapping
+            .rules
+            .iter()
+            .flat_map(|x| x.clocks.iter().flatten())
+            .map(String::as_str)
+            .collect();
 
-        let file = Hdf5File::open(&file)?;
-        let datasets = file.get_datasets();
+        // topics timed by each clock
+        let mut clock_topics: BTreeMap<String, Vec<String>> = BTreeMap::new();
 
-        let mut channel_id: u16 = 0;
+        // groups merged into one topic by their rule
+        let mut merged_groups: BTreeMap<String, PendingGroup> = BTreeMap::new();
 
         for dataset in datasets.values() {
-            if dataset.name.contains(".timestamp") {
+            let rule = mapping.resolve(&dataset.name);
+
+            let Some((group, basename)) = dataset.name.rsplit_once('/') else {
+                continue;
+            };
+
+            let is_timestamp = dataset.name.contains(".timestamp")
+                || flavour.group_timestamp_names().contains(&basename)
+                || is_time_companion(basename, &mapping_clocks);
+
+            let is_hidden = is_timestamp || flavour.is_hidden(dataset);
+
+            if rule.is_excluded() || (is_hidden && !rule.is_included()) {
                 continue;
             }
 
-            let mut timestamp_dataset = datasets.get(&format!("{}.timestamp", dataset.name));
+            let attr_encoding = dataset
+                .attrs
+                .get("foxglove_encoding")
+                .and_then(Attribute::as_str)
+                .map(str::parse::<MessageEncoding>);
+
+            let encoding = match (rule.encoding, attr_encoding) {
+                (Some(encoding), _) => Some(encoding),
+                (None, Some(Ok(encoding))) => Some(encoding),
+                (None, Some(Err(e))) => {
+                    init = init.add_problem(
+                        Problem::warn(format!(
+                            "Invalid foxglove_encoding for {}: {e}",
+                            dataset.name
+                        ))
+                        .tip("Supported encodings are \"protobuf\" and \"json\"."),
+                    );
+                    default_encoding
+                }
+                (None, None) => default_encoding,
+            };
+
+            let options = TopicOptions {
+                frame_id: rule.frame_id.clone(),
+                encoding,
+                transposed_images: flavour.transposes_images(),
+            };
+
+            let mut dataset = dataset.clone();
+
+            let priority = rule.clocks.as_deref().unwrap_or_default();
+            let mut clocks = clocks(&flavour, datasets, &dataset, priority);
+
+            // the clocks named by the rule first, in order, then the others by default priority
+            clocks.sort_by_key(|x| {
+                priority
+                    .iter()
+                    .position(|name| *name == x.name)
+                    .unwrap_or(priority.len())
+            });
+
+            let mut timestamp_dataset = clocks.first().map(|x| x.dataset);
+
+            let mut publish_dataset = time_companion(datasets, &dataset.name, &PUBLISH_TIME_NAMES);
 
             error!("ATTRS: {:?}", dataset.attrs);
 
-            if let Some(Attribute::Vlen(dimensions)) = dataset.attrs.get("DIMENSION_LIST") {
-                for (index, dimension) in dimensions.iter().enumerate() {
-                    let Attribute::Reference(name) = dimension else {
+            let time_axis = rule
+                .time_axis
+                .or_else(|| clocks.first().and_then(|x| x.time_axis));
+
+            if let Some(axis) = time_axis
+                && axis >= dataset.stored_dimensions.len()
+            {
+                init = init.add_problem(
+                    Problem::warn(format!(
+                        "Invalid time axis {axis} for {}, which has {} dimensions",
+                        dataset.name,
+                        dataset.stored_dimensions.len()
+                    ))
+                    .tip("Check the time_axis of the mapping rules matching this dataset."),
+                );
+                continue;
+            }
+
+            if let Some(timestamp) = &rule.timestamp {
+                let name = Rule::expand(timestamp, &dataset.name);
+
+                let Some(x) = datasets.get(&name) else {
+                    init = init.add_problem(
+                        Problem::warn(format!(
+                            "Missing timestamp dataset {name} for {}",
+                            dataset.name
+                        ))
+                        .tip("Check the timestamp of the mapping rules matching this dataset."),
+                    );
+                    continue;
+                };
+
+                timestamp_dataset = Some(x);
+            }
+
+            if let Some(timestamp) = &rule.publish_timestamp {
+                let name = Rule::expand(timestamp, &dataset.name);
+
+                let Some(x) = datasets.get(&name) else {
+                    init = init.add_problem(
+                        Problem::warn(format!(
+                            "Missing publish timestamp dataset {name} for {}",
+                            dataset.name
+                        ))
+                        .tip(
+                            "Check the publish_timestamp of the mapping rules matching this dataset.",
+                        ),
+                    );
+                    continue;
+                };
+
+                publish_dataset = Some(x);
+            }
+
+            let time_base = |x: &Dataset| rule_time_base(&flavour, &rule, x);
+
+            let log_times = match timestamp_dataset {
+                Some(timestamp_dataset) => {
+                    let rows = timestamp_dataset.stored_dimensions.iter().product();
+                    flavour.prepare(&mut dataset, rows);
+
+                    if let Some(axis) = time_axis {
+                        dataset.set_layout(axis, dataset.column_major);
+                    }
+
+                    match topic_timestamps(
+                        &dataset.name,
+                        timestamp_dataset,
+                        time_base(timestamp_dataset),
+                    ) {
+                        Ok(x) => x,
+                        Err(problem) => {
+                            init = init.add_problem(problem);
+                            continue;
+                        }
+                    }
+                }
+                None => {
+                    let Some(sampling) = flavour.sampling(&dataset, datasets) else {
+                        init = init.add_problem(Problem::warn(format!("Missing timestamps for {}", dataset.name))
+                            .tip(format!("Ensure that the dataset {}.timestamp or a timestamp dataset in its group exists, or specify a time dataset with DIMENSION_LIST attribute.", dataset.name)));
                         continue;
                     };
 
-                    let Some(dataset) = datasets.get(name) else {
+                    let rows = dataset
+                        .stored_dimensions
+                        .get(time_axis.unwrap_or_default())
+                        .copied()
+                        .unwrap_or(1);
+                    flavour.prepare(&mut dataset, rows);
+
+                    if let Some(axis) = time_axis {
+                        dataset.set_layout(axis, dataset.column_major);
+                    }
+
+                    // the layout may publish the dataset as fewer rows, like a single map
+                    let rows = dataset.dimensions.first().copied().unwrap_or(1);
+                    sampled_times(sampling, rows)
+                }
+            };
+
+            let publish_times = match publish_dataset {
+                Some(x) => match topic_timestamps(&dataset.name, x, time_base(x)) {
+                    Ok(x) => Some(x),
+                    Err(problem) => {
+                        init = init.add_problem(problem);
                         continue;
-                    };
+                    }
+                },
+                None => None,
+            };
 
-                    if dataset.name.contains("time") || dataset.name.contains("Time") {
-                        timestamp_dataset = Some(dataset);
+            let publish_times =
+                match matching_publish_times(&dataset.name, log_times.len(), publish_times) {
+                    Ok(x) => x,
+                    Err(problem) => {
+                        init = init.add_problem(problem);
+                        None
                     }
+                };
+
+            let (log_times, publish_times) =
+                playback_times(log_times, publish_times, rule.playback.unwrap_or_default());
+
+            let (timestamps, message_count) = index_timestamps(log_times);
+
+            // the clock of each topic is reported, so mixed-clock files are read knowingly
+            let clock = match timestamp_dataset {
+                Some(x) => clocks
+                    .iter()
+                    .find(|clock| clock.dataset.name == x.name)
+                    .map(|clock| clock.name.clone())
+                    .unwrap_or_else(|| x.name.rsplit('/').next().unwrap_or_default().to_string()),
+                None => "rate".to_string(),
+            };
+
+            // numeric datasets of a merged group sharing its clock are fields of the group's topic
+            let group_rule = mapping.resolve(group);
+
+            if group_rule.is_merged()
+                && !flavour.publishes_group(group, datasets)
+                && matches!(dataset.type_, DatasetType::Integer | DatasetType::Float)
+                && let Some(timestamp_dataset) = timestamp_dataset
+                && dataset.dimensions.first() == Some(&message_count)
+            {
+                let pending =
+                    merged_groups
+                        .entry(group.to_string())
+                        .or_insert_with(|| PendingGroup {
+                            timestamp: timestamp_dataset.clone(),
+                            clock: clock.clone(),
+                            publish_timestamp: publish_dataset.map(|x| x.name.clone()),
+                            timestamps: timestamps.clone(),
+                            message_count,
+                            publish_times: publish_times.clone(),
+                            fields: vec![],
+                        });
+
+                if pending.timestamp.name == timestamp_dataset.name
+                    && pending.publish_timestamp.as_deref()
+                        == publish_dataset.map(|x| x.name.as_str())
+                {
+                    pending.fields.push((basename.to_string(), dataset));
+                    continue;
                 }
             }
 
-            let Some(timestamp_dataset) = timestamp_dataset else {
-                init = init.add_problem(Problem::warn(format!("Missing timestamps for {}", dataset.name))
-                    .tip(format!("Ensure that the dataset {}.timestamp exists or specify a time dataset with DIMENSION_LIST attribute.", dataset.name)));
+            let dataset = &dataset;
+
+            let topic_name = match &rule.topic {
+                Some(topic) => Rule::expand(topic, &dataset.name),
+                None => dataset.name.clone(),
+            };
+
+            let topic_name = if shared.contains(&dataset.name) {
+                format!("{prefix}{topic_name}")
+            } else {
+                topic_name
+            };
+
+            let converters = match rule.converter {
+                Some(kind) => {
+                    let Some(converter) = self.converters.get(kind) else {
+                        init = init.add_problem(Problem::warn(format!(
+                            "Unknown converter {kind:?} for {}",
+                            dataset.name
+                        )));
+                        continue;
+                    };
+
+                    let plan = match converter.matches(dataset, datasets, &options) {
+                        Ok(Some(plan)) => Ok(plan),
+                        Ok(None) => {
+                            init = init.add_problem(
+                                Problem::warn(format!(
+                                    "Cannot publish {} with the {kind:?} converter",
+                                    dataset.name
+                                ))
+                                .tip(converter.requirements()),
+                            );
+                            continue;
+                        }
+                        Err(e) => Err(e),
+                    };
+
+                    vec![(converter, plan)]
+                }
+                None => self.converters.detect(dataset, datasets, &options),
+            };
+
+            if converters.is_empty() {
+                init = init.add_problem(
+                    Problem::warn(format!("Unsupported format for {}", dataset.name)).tip(format!(
+                        "The dataset type of {:?} is not supported.",
+                        dataset.type_
+                    )),
+                );
                 continue;
+            }
+
+            // the suffixes end the borrow of the registry before the topics are added
+            let converters: Vec<_> = converters
+                .into_iter()
+                .map(|(converter, plan)| (converter.topic_suffix(), plan))
+                .collect();
+
+            for (suffix, plan) in converters {
+                let name = match suffix {
+                    Some(suffix) => format!("{topic_name}{suffix}"),
+                    None => topic_name.clone(),
+                };
+
+                let plan = match plan {
+                    Ok(plan) => plan,
+                    Err(e) => {
+                        init = init.add_problem(
+                            Problem::warn(format!("Cannot publish {name}"))
+                                .tip(format!("Failed to read {}: {e:#}", dataset.name)),
+                        );
+                        continue;
+                    }
+                };
+
+                let topic = Topic {
+                    dataset: dataset.clone(),
+                    plan,
+                    timestamps: timestamps.clone(),
+                    publish_times: publish_times.clone(),
+                };
+
+                if let Err(e) = self.add_topic(&mut init, &name, message_count, topic) {
+                    init = init.add_problem(publish_problem(&name, e));
+                    continue;
+                }
+
+                clock_topics.entry(clock.clone()).or_default().push(name);
+            }
+        }
+
+        // merged groups are timed by the clock of their datasets
+        for (group, pending) in merged_groups {
+            let rule = mapping.resolve(&group);
+
+            let topic_name = match &rule.topic {
+                Some(topic) => Rule::expand(topic, &group),
+                None => group.clone(),
             };
 
-            let mut timestamps: TimestampIndex = Default::default();
+            let topic_name = if shared.contains(&pending.timestamp.name) {
+                format!("{prefix}{topic_name}")
+            } else {
+                topic_name
+            };
 
-            let (timestamp_data, _) = timestamp_dataset.read::<u64>()?;
+            let group = MergedGroup {
+                name: group,
+                fields: pending.fields,
+            };
+
+            let schema = match group.schema() {
+                Ok(x) => x,
+                Err(e) => {
+                    init = init.add_problem(publish_problem(&topic_name, e));
+                    continue;
+                }
+            };
+
+            let plan = json_plan(schema, move |index, _| {
+                serialize_merged_group(index, &group)
+            });
+
+            let topic = Topic {
+                dataset: pending.timestamp,
+                plan,
+                timestamps: pending.timestamps,
+                publish_times: pending.publish_times,
+            };
+
+            if let Err(e) = self.add_topic(&mut init, &topic_name, pending.message_count, topic) {
+                init = init.add_problem(publish_problem(&topic_name, e));
+                continue;
+            }
+
+            clock_topics
+                .entry(pending.clock)
+                .or_default()
+                .push(topic_name);
+        }
 
-            let mut message_count = 0;
+        // pandas DataFrames are published as one topic, timed by their index
+        for group in flavour.frames() {
+            let rule = mapping.resolve(group);
 
-            for (i, timestamp) in timestamp_data.into_iter().enumerate() {
-                let entry = timestamps.entry(timestamp * 1000 * 1000).or_default();
-                entry.push(i as u64);
-                message_count += 1;
+            if rule.is_excluded() {
+                continue;
             }
 
-            match dataset.type_ {
-                DatasetType::Integer => {
-                    self.topics.push(Topic {
-                        dataset: dataset.clone(),
-                        serialize_message: serialize_integer_raw,
-                        timestamps: timestamps.clone(),
-                    });
-
-                    init.add_encode::<RawIntegerDataset>()?
-                        .add_channel_with_id(channel_id, &dataset.name)
-                        .expect("not in use")
-                        .message_count(message_count);
-
-                    channel_id += 1;
-
-                    if dataset.is_image_topic() {
-                        if dataset.dimensions.len() == 3 {
-                            self.topics.push(Topic {
-                                dataset: dataset.clone(),
-                                serialize_message: serialize_mono16_raw_image,
-                                timestamps: timestamps.clone(),
-                            });
-
-                            init.add_encode::<RawImage>()?
-                                .add_channel_with_id(
-                                    channel_id,
-                                    &format!("{}/as_image", dataset.name),
-                                )
-                                .expect("not in use")
-                                .message_count(message_count);
-
-                            channel_id += 1;
-                        }
+            let Some(frame) = DataFrame::from_group(group, datasets) else {
+                init = init.add_problem(
+                    Problem::warn(format!("Unsupported DataFrame {group}"))
+                        .tip("DataFrames need numeric columns and a numeric or datetime index."),
+                );
+                continue;
+            };
 
-                        if dataset.dimensions.len() == 4 {
-                            self.topics.push(Topic {
-                                dataset: dataset.clone(),
-                                serialize_message: serialize_rgb8_raw_image,
-                                timestamps,
-                            });
-
-                            init.add_encode::<RawImage>()?
-                                .add_channel_with_id(
-                                    channel_id,
-                                    &format!("{}/as_image", dataset.name),
-                                )
-                                .expect("not in use")
-                                .message_count(message_count);
-
-                            channel_id += 1;
+            let base = match (rule.unit, frame.datetime_index) {
+                (Some(unit), _) => unit.into(),
+                (None, true) => TimeUnit::Ns.into(),
+                (None, false) => flavour.time_base(&frame.index, time_scale(&frame.index)),
+            };
+
+            let log_times = match topic_timestamps(group, &frame.index, base) {
+                Ok(x) => x,
+                Err(problem) => {
+                    init = init.add_problem(problem);
+                    continue;
+                }
+            };
+
+            let publish_times = match &rule.publish_timestamp {
+                Some(timestamp) => {
+                    let name = Rule::expand(timestamp, group);
+
+                    let Some(x) = datasets.get(&name) else {
+                        init = init.add_problem(
+                            Problem::warn(format!(
+                                "Missing publish timestamp dataset {name} for {group}"
+                            ))
+                            .tip("Check the publish_timestamp of the mapping rules matching this DataFrame."),
+                        );
+                        continue;
+                    };
+
+                    match topic_timestamps(group, x, rule_time_base(&flavour, &rule, x)) {
+                        Ok(x) => Some(x),
+                        Err(problem) => {
+                            init = init.add_problem(problem);
+                            continue;
                         }
                     }
                 }
-                DatasetType::Float => {
-                    self.topics.push(Topic {
-                        dataset: dataset.clone(),
-                        serialize_message: serialize_float_raw,
-                        timestamps,
-                    });
-
-                    init.add_encode::<RawFloatDataset>()?
-                        .add_channel_with_id(channel_id, &dataset.name)
-                        .expect("not in use")
-                        .message_count(message_count);
-
-                    channel_id += 1;
+                None => None,
+            };
+
+            let publish_times = match matching_publish_times(group, log_times.len(), publish_times)
+            {
+                Ok(x) => x,
+                Err(problem) => {
+                    init = init.add_problem(problem);
+                    None
                 }
-                t => {
+            };
+
+            let (log_times, publish_times) =
+                playback_times(log_times, publish_times, rule.playback.unwrap_or_default());
+
+            let (timestamps, message_count) = index_timestamps(log_times);
+
+            let topic_name = match &rule.topic {
+                Some(topic) => Rule::expand(topic, group),
+                None => group.to_string(),
+            };
+
+            let topic_name = if shared.contains(&frame.index.name) {
+                format!("{prefix}{topic_name}")
+            } else {
+                topic_name
+            };
+
+            let index = frame.index.clone();
+
+            let schema = match frame.schema() {
+                Ok(x) => x,
+                Err(e) => {
+                    init = init.add_problem(publish_problem(&topic_name, e));
+                    continue;
+                }
+            };
+
+            let plan = json_plan(schema, move |index, _| serialize_data_frame(index, &frame));
+
+            let topic = Topic {
+                dataset: index,
+                plan,
+                timestamps,
+                publish_times,
+            };
+
+            if let Err(e) = self.add_topic(&mut init, &topic_name, message_count, topic) {
+                init = init.add_problem(publish_problem(&topic_name, e));
+                continue;
+            }
+
+            clock_topics
+                .entry("index".to_string())
+                .or_default()
+                .push(topic_name);
+        }
+
+        for (clock, names) in clock_topics {
+            init = init.add_problem(
+                Problem::info(format!("Timed by the {clock} clock: {}", names.join(", ")))
+                    .tip("Set the clocks or timestamp of a mapping rule to time a topic by another clock."),
+            );
+        }
+
+        init
+    }
+}
+
+impl DataLoader for Hdf5Loader {
+    type MessageIterator = Hdf5Iterator;
+    type Error = anyhow::Error;
+
+    fn new(args: foxglove_data_loader::DataLoaderArgs) -> Self {
+        // mapping files can be opened together with the hdf5 file
+        let (mapping_paths, paths): (Vec<String>, Vec<String>) = args
+            .paths
+            .into_iter()
+            .partition(|x| Mapping::is_mapping_path(x));
+
+        Self {
+            paths,
+            mapping_paths,
+            mapping: Mapping::default(),
+            converters: Registry::default(),
+            files: vec![],
+            topics: vec![],
+            dropped_topics: 0,
+        }
+    }
+
+    fn initialize(&mut self) -> Result<foxglove_data_loader::Initialization, Self::Error> {
+        init_lzf();
+
+        let mut init = Initialization::builder();
+
+        for path in self.mapping_paths.iter() {
+            match Mapping::open(path) {
+                Ok(mapping) => self.mapping.extend(mapping),
+                Err(e) => {
                     init = init.add_problem(
-                        Problem::warn(format!("Unsupported format for {}", dataset.name))
-                            .tip(format!("The dataset type of {t:?} is not supported.")),
+                        Problem::error(format!("Failed to read mapping file {path}"))
+                            .tip(format!("{e:#}")),
                     );
+                }
+            }
+        }
+
+        if self.paths.is_empty() {
+            init = init.add_problem(
+                Problem::error("No HDF5 file to open")
+                    .tip("Mapping files are applied to the HDF5 files opened together with them."),
+            );
+        }
+
+        wasm_vfs::set_available_paths(self.paths.clone());
+
+        let mut files = vec![];
+
+        for set in wasm_vfs::group_files(&self.paths) {
+            let set = match set {
+                Ok(set) => set,
+                Err(problem) => {
+                    init = init.add_problem(problem);
+                    continue;
+                }
+            };
+
+            let path = set.path().to_string();
+
+            let file = match Hdf5File::open_set(&set) {
+                Ok(file) => file,
+                Err(e) => {
+                    init = init.add_problem(Problem::error(format!("Failed to open {path}")).tip(
+                        format!(
+                            "{e:#}. Only HDF5 files and the formats based on it can be opened."
+                        ),
+                    ));
+                    continue;
+                }
+            };
+
+            let mut datasets = file.get_datasets();
+            let mut missing = BTreeSet::new();
+
+            // objects in other files are only reachable when those files were opened as well
+            for link in file.external_links() {
+                if wasm_vfs::resolve_path(&link.file).is_none() {
+                    missing.insert(link.file);
                     continue;
                 }
+
+                datasets.extend(file.get_linked_datasets(&link));
+            }
+
+            for dataset in datasets.values() {
+                for source in dataset.virtual_files() {
+                    if source != "." && wasm_vfs::resolve_path(&source).is_none() {
+                        missing.insert(source);
+                    }
+                }
+            }
+
+            if !missing.is_empty() {
+                let missing: Vec<String> = missing.into_iter().collect();
+
+                init = init.add_problem(
+                    Problem::warn(format!(
+                        "Missing files referenced by {path}: {}",
+                        missing.join(", ")
+                    ))
+                    .tip("External links and virtual datasets read from these files, open them together with this file."),
+                );
+            }
+
+            files.push((path, file, datasets));
+        }
+
+        // datasets found in several files are told apart by prefixing their topics
+        let mut seen = BTreeSet::new();
+        let mut shared = BTreeSet::new();
+
+        for (_, _, datasets) in files.iter() {
+            for name in datasets.keys() {
+                if !seen.insert(name.clone()) {
+                    shared.insert(name.clone());
+                }
             }
         }
 
+        let prefixes = file_prefixes(files.iter().map(|(path, _, _)| path.as_str()));
+
+        for ((path, file, datasets), prefix) in files.into_iter().zip(prefixes) {
+            init = self.add_file(init, &path, &prefix, &file, &datasets, &shared);
+            self.files.push(file);
+        }
+
+        if self.dropped_topics > 0 {
+            init = init.add_problem(
+                Problem::error(format!(
+                    "{} topics were not published",
+                    self.dropped_topics
+                ))
+                .tip("A data source can have at most 65536 topics, exclude datasets with mapping rules to publish the others."),
+            );
+        }
+
         let min = self
             .topics
             .iter()
@@ -310,8 +1214,6 @@ impl DataLoader for Hdf5Loader {
             init = init.end_time(*max);
         }
 
-        self.file = Some(file);
-
         Ok(init.build())
     }
 
@@ -357,3 +1259,54 @@ impl DataLoader for Hdf5Loader {
 }
 
 foxglove_data_loader::export!(Hdf5Loader);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_companions_by_name_then_group() {
+        let datasets: BTreeMap<String, Dataset> = [
+            "/imu/accel",
+            "/imu/accel.header_stamp",
+            "/imu/publish_time",
+            "/imu/timestamp",
+        ]
+        .into_iter()
+        .map(|x| {
+            (
+                x.to_string(),
+                Dataset::in_memory(x, DatasetType::Float, &[10]),
+            )
+        })
+        .collect();
+
+        let name = |x: Option<&Dataset>| x.map(|x| x.name.clone());
+
+        assert_eq!(
+            name(time_companion(&datasets, "/imu/accel", &PUBLISH_TIME_NAMES)),
+            Some("/imu/accel.header_stamp".to_string())
+        );
+        assert_eq!(
+            name(time_companion(&datasets, "/imu/gyro", &PUBLISH_TIME_NAMES)),
+            Some("/imu/publish_time".to_string())
+        );
+        assert_eq!(
+            name(time_companion(&datasets, "/gps/fix", &PUBLISH_TIME_NAMES)),
+            None
+        );
+
+        assert!(is_time_companion("accel.header_stamp", &[]));
+        assert!(is_time_companion("gps_time", &[]));
+        assert!(is_time_companion("accel.sensor_time", &["sensor_time"]));
+        assert!(!is_time_companion("accel", &[]));
+    }
+
+    #[test]
+    fn timestamps_need_numbers() {
+        let names = Dataset::in_memory("/names", DatasetType::String, &[3]);
+
+        assert!(read_timestamps(&names, TimeUnit::S.into()).is_err());
+        assert!(topic_timestamps("/imu/accel", &names, TimeUnit::S.into()).is_err());
+    }
+}
@@ -0,0 +1,348 @@
+use std::{io::Read, str::FromStr};
+
+use anyhow::{Context, bail};
+use serde::Deserialize;
+
+use crate::messages::MessageEncoding;
+
+/// Extensions of mapping files, distinct from other JSON and TOML files.
+const MAPPING_EXTENSIONS: [&str; 2] = [".h5map.json", ".h5map.toml"];
+
+/// Rules controlling how datasets become topics, overriding the built-in heuristics.
+///
+/// ```toml
+/// [[rules]]
+/// match = "/imu/**"
+/// timestamp = "{group}/stamp"
+/// unit = "s"
+/// frame_id = "imu"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Mapping {
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+}
+
+/// A rule applied to every dataset whose path matches `match`. When several rules match a
+/// dataset, the later rules override the fields set by earlier ones.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Glob matched against the dataset path. `*` matches within a path segment, `**` matches
+    /// across segments and `?` matches a single character.
+    #[serde(rename = "match")]
+    pub pattern: String,
+    /// Path of the timestamp dataset, see [`Rule::expand`] for the placeholders.
+    pub timestamp: Option<String>,
+    /// Unit of the values in the timestamp dataset.
+    pub unit: Option<TimeUnit>,
+    /// Time scale of the values in the timestamp datasets, instead of their `time_scale`
+    /// attribute.
+    pub time_scale: Option<TimeScale>,
+    /// Clocks tried in order for the timestamps, like `["gps_time", "timestamp"]`, each a
+    /// `{name}.{clock}` dataset or a `{clock}` dataset in the group. Clocks which aren't listed
+    /// or found follow in the default priority.
+    pub clocks: Option<Vec<String>>,
+    /// Path of the dataset holding the publish times of the messages, like the stamps of their
+    /// headers, when the timestamp dataset holds the times they were received.
+    pub publish_timestamp: Option<String>,
+    /// The time ordering the messages in playback.
+    pub playback: Option<PlaybackTime>,
+    /// Axis of the stored dataset indexed by the timestamps, like 2 for a `(lat, lon, time)`
+    /// variable. Defaults to the axis of the time dimension scale, or the first axis.
+    pub time_axis: Option<usize>,
+    /// Converter used to publish the dataset, instead of the detected ones.
+    pub converter: Option<ConverterKind>,
+    /// Topic name, see [`Rule::expand`] for the placeholders.
+    pub topic: Option<String>,
+    pub frame_id: Option<String>,
+    pub encoding: Option<MessageEncoding>,
+    /// Publish the numeric datasets of the matched group which share a timestamp dataset as one
+    /// JSON topic named after the group, with a field for each dataset.
+    pub merge: Option<bool>,
+    /// Publish datasets which would otherwise be skipped, like timestamp datasets.
+    pub include: Option<bool>,
+    pub exclude: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeUnit {
+    S,
+    #[default]
+    Ms,
+    Us,
+    Ns,
+}
+
+impl TimeUnit {
+    pub fn nanos(&self) -> u64 {
+        match self {
+            TimeUnit::S => 1_000_000_000,
+            TimeUnit::Ms => 1_000_000,
+            TimeUnit::Us => 1_000,
+            TimeUnit::Ns => 1,
+        }
+    }
+}
+
+/// The time scale of timestamps, which differ from UTC by the leap seconds since their epoch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum TimeScale {
+    #[default]
+    #[serde(rename = "UTC", alias = "utc")]
+    Utc,
+    /// GPS time, counted from 1980-01-06 and 19 s behind TAI.
+    #[serde(rename = "GPS", alias = "gps")]
+    Gps,
+    /// International Atomic Time, counted from 1958-01-01.
+    #[serde(rename = "TAI", alias = "tai")]
+    Tai,
+}
+
+impl FromStr for TimeScale {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_uppercase().as_str() {
+            "UTC" => Ok(Self::Utc),
+            "GPS" => Ok(Self::Gps),
+            "TAI" => Ok(Self::Tai),
+            _ => bail!("unknown time scale {s}"),
+        }
+    }
+}
+
+/// The time of messages used as their log time, which orders their playback. The other time is
+/// their publish time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaybackTime {
+    /// The time the messages were received, from the timestamp dataset.
+    #[default]
+    LogTime,
+    /// The time the messages were published, from the publish timestamp dataset.
+    PublishTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConverterKind {
+    Raw,
+    Fields,
+    Image,
+    Grid,
+    LaserScan,
+    Audio,
+    LocationFix,
+    #[serde(rename = "geojson")]
+    GeoJson,
+    Coordinates,
+}
+
+impl Rule {
+    fn merge(&mut self, other: &Rule) {
+        macro_rules! merge {
+            ($($field:ident),*) => {
+                $(
+                    if other.$field.is_some() {
+                        self.$field = other.$field.clone();
+                    }
+                )*
+            };
+        }
+
+        merge!(
+            timestamp,
+            unit,
+            time_scale,
+            clocks,
+            publish_timestamp,
+            playback,
+            time_axis,
+            converter,
+            topic,
+            frame_id,
+            encoding,
+            merge,
+            include,
+            exclude
+        );
+    }
+
+    pub fn is_excluded(&self) -> bool {
+        self.exclude == Some(true)
+    }
+
+    pub fn is_included(&self) -> bool {
+        self.include == Some(true)
+    }
+
+    pub fn is_merged(&self) -> bool {
+        self.merge == Some(true)
+    }
+
+    /// Replace `{path}`, `{group}` and `{name}` in a template with the dataset's full path, the
+    /// path of its group and its name within the group.
+    pub fn expand(template: &str, path: &str) -> String {
+        let (group, name) = path.rsplit_once('/').unwrap_or(("", path));
+
+        template
+            .replace("{path}", path)
+            .replace("{group}", group)
+            .replace("{name}", name)
+    }
+}
+
+impl Mapping {
+    pub fn parse(path: &str, contents: &str) -> anyhow::Result<Self> {
+        if path.ends_with(".toml") {
+            Ok(toml::from_str(contents)?)
+        } else {
+            Ok(serde_json::from_str(contents)?)
+        }
+    }
+
+    /// Read a mapping file provided alongside the HDF5 file.
+    pub fn open(path: &str) -> anyhow::Result<Self> {
+        #[cfg(target_arch = "wasm32")]
+        let mut reader = foxglove_data_loader::reader::open(path);
+        #[cfg(not(target_arch = "wasm32"))]
+        let mut reader = std::fs::File::open(path)?;
+
+        let mut contents = String::new();
+        reader.read_to_string(&mut contents)?;
+
+        Self::parse(path, &contents).with_context(|| format!("failed to parse {path}"))
+    }
+
+    /// Whether a path is a mapping file, named like `recording.h5map.toml`, as other JSON and
+    /// TOML files aren't opened by the loader.
+    pub fn is_mapping_path(path: &str) -> bool {
+        MAPPING_EXTENSIONS.iter().any(|x| path.ends_with(x))
+    }
+
+    pub fn extend(&mut self, other: Mapping) {
+        self.rules.extend(other.rules);
+    }
+
+    /// Combine every rule matching the path, in order.
+    pub fn resolve(&self, path: &str) -> Rule {
+        let mut rule = Rule::default();
+
+        for x in self.rules.iter().filter(|x| glob_match(&x.pattern, path)) {
+            rule.merge(x);
+        }
+
+        rule
+    }
+}
+
+fn glob_match(pattern: &str, path: &str) -> bool {
+    fn matches(pattern: &[u8], path: &[u8]) -> bool {
+        match pattern {
+            [] => path.is_empty(),
+            [b'*', b'*', rest @ ..] => (0..=path.len()).any(|i| matches(rest, &path[i..])),
+            [b'*', rest @ ..] => (0..=path.len())
+                .take_while(|i| *i == 0 || path[i - 1] != b'/')
+                .any(|i| matches(rest, &path[i..])),
+            [b'?', rest @ ..] => !path.is_empty() && path[0] != b'/' && matches(rest, &path[1..]),
+            [c, rest @ ..] => path.first() == Some(c) && matches(rest, &path[1..]),
+        }
+    }
+
+    matches(pattern.as_bytes(), path.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_match_segments() {
+        assert!(glob_match("/imu/*", "/imu/accel"));
+        assert!(!glob_match("/imu/*", "/imu/raw/accel"));
+        assert!(glob_match("/imu/**", "/imu/raw/accel"));
+        assert!(glob_match("/**/accel", "/a/b/accel"));
+        assert!(glob_match("/cam?/image", "/cam0/image"));
+        assert!(!glob_match("/cam?/image", "/cam/image"));
+        assert!(!glob_match("/imu/accel", "/imu/accel2"));
+    }
+
+    #[test]
+    fn later_rules_override_earlier_ones() {
+        let mapping = Mapping::parse(
+            "mapping.toml",
+            r#"
+            [[rules]]
+            match = "/imu/**"
+            timestamp = "{group}/stamp"
+            unit = "s"
+
+            [[rules]]
+            match = "/imu/accel"
+            unit = "ns"
+            topic = "/accel"
+
+            [[rules]]
+            match = "/imu"
+            merge = true
+
+            [[rules]]
+            match = "/other"
+            exclude = true
+            "#,
+        )
+        .unwrap();
+
+        let rule = mapping.resolve("/imu/accel");
+        assert_eq!(rule.timestamp.as_deref(), Some("{group}/stamp"));
+        assert_eq!(rule.unit, Some(TimeUnit::Ns));
+        assert_eq!(rule.topic.as_deref(), Some("/accel"));
+        assert!(!rule.is_excluded());
+        assert!(!rule.is_merged());
+        assert!(mapping.resolve("/imu").is_merged());
+
+        assert_eq!(mapping.resolve("/imu/gyro").unit, Some(TimeUnit::S));
+        assert!(mapping.resolve("/other").is_excluded());
+    }
+
+    #[test]
+    fn parse_json_and_reject_unknown_fields() {
+        let mapping = Mapping::parse(
+            "mapping.json",
+            r#"{ "rules": [{ "match": "/a", "converter": "laser_scan", "include": true, "time_axis": 2, "time_scale": "GPS" }] }"#,
+        )
+        .unwrap();
+
+        assert_eq!(mapping.rules[0].time_axis, Some(2));
+        assert_eq!(mapping.rules[0].time_scale, Some(TimeScale::Gps));
+
+        assert_eq!(mapping.rules[0].converter, Some(ConverterKind::LaserScan));
+        assert!(mapping.rules[0].is_included());
+
+        let unknown_unit = r#"{ "rules": [{ "match": "/a", "unit": "h" }] }"#;
+        assert!(Mapping::parse("mapping.json", unknown_unit).is_err());
+
+        let unknown_field = r#"{ "rules": [{ "match": "/a", "typo": 1 }] }"#;
+        assert!(Mapping::parse("mapping.json", unknown_field).is_err());
+    }
+
+    #[test]
+    fn mapping_files_have_their_own_extensions() {
+        assert!(Mapping::is_mapping_path("/data/run.h5map.toml"));
+        assert!(Mapping::is_mapping_path("run.h5map.json"));
+        assert!(!Mapping::is_mapping_path("package.json"));
+        assert!(!Mapping::is_mapping_path("Cargo.toml"));
+    }
+
+    #[test]
+    fn expand_placeholders() {
+        assert_eq!(Rule::expand("{group}/stamp", "/imu/accel"), "/imu/stamp");
+        assert_eq!(
+            Rule::expand("/out{path}_{name}", "/imu/accel"),
+            "/out/imu/accel_accel"
+        );
+    }
+}
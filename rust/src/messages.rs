@@ -1,30 +1,95 @@
 use core::time;
+use std::{collections::BTreeMap, str::FromStr};
 
-use crate::hdf5::Dataset;
+use crate::hdf5::{Attribute, Dataset, DatasetType};
 use anyhow::bail;
-use foxglove::{Encode, schemas::RawImage};
+use foxglove::{
+    Encode,
+    schemas::{
+        GeoJson, Grid, LaserScan, LocationFix, PackedElementField, Pose, Quaternion, RawAudio,
+        RawImage, Vector2, Vector3, packed_element_field::NumericType,
+    },
+};
+use serde::Deserialize;
 
-pub fn serialize_mono16_raw_image(index: u64, dataset: &Dataset) -> anyhow::Result<Vec<u8>> {
-    if dataset.dimensions.len() != 3 {
-        bail!("invalid dimensions for mono16 image");
+/// The frame of a dataset's messages, from its `frame_id` attribute.
+pub fn frame_id(dataset: &Dataset) -> String {
+    dataset
+        .attrs
+        .get("frame_id")
+        .and_then(Attribute::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Pixels of a frame stored as `(width, height, channels)`, like NWB `ImageSeries`, in the
+/// `(height, width, channels)` order of images.
+fn swap_image_axes<T: Copy>(pixels: Vec<T>, width: usize, height: usize) -> Vec<T> {
+    let channels = pixels.len() / (width * height).max(1);
+    let mut out = Vec::with_capacity(pixels.len());
+
+    for y in 0..height {
+        for x in 0..width {
+            let start = (x * height + y) * channels;
+            out.extend_from_slice(&pixels[start..start + channels]);
+        }
     }
 
-    let mut data = dataset.read_at_index::<u16>(index)?;
+    out
+}
+
+/// The width and height of the frames of an image dataset, stored as `(time, height, width)`
+/// or, when `transposed`, as `(time, width, height)`.
+fn image_size(dataset: &Dataset, transposed: bool) -> (usize, usize) {
+    let [first, second] = [dataset.dimensions[1], dataset.dimensions[2]].map(|x| x as usize);
 
-    for d in data.iter_mut() {
-        *d = d.to_le();
+    match transposed {
+        true => (first, second),
+        false => (second, first),
+    }
+}
+
+/// Serialize a grayscale frame, as `mono8` for 1 byte integers and `mono16` otherwise.
+pub fn serialize_mono_raw_image(
+    index: u64,
+    dataset: &Dataset,
+    frame_id: &str,
+    transposed: bool,
+) -> anyhow::Result<Vec<u8>> {
+    if dataset.dimensions.len() != 3 {
+        bail!("invalid dimensions for mono image");
     }
 
-    let data = unsafe { data.align_to::<u8>().1.to_vec() };
+    let (width, height) = image_size(dataset, transposed);
+
+    let (data, encoding, pixel_size) = if dataset.type_size == 1 {
+        let mut data = dataset.read_at_index::<u8>(index)?;
+
+        if transposed {
+            data = swap_image_axes(data, width, height);
+        }
+
+        (data, "mono8", 1)
+    } else {
+        let mut data = dataset.read_at_index::<u16>(index)?;
+
+        if transposed {
+            data = swap_image_axes(data, width, height);
+        }
+
+        let data: Vec<u8> = data.iter().flat_map(|x| x.to_le_bytes()).collect();
+
+        (data, "mono16", 2)
+    };
 
     let message = RawImage {
         timestamp: None,
         data: data.into(),
-        step: (dataset.dimensions[2] * 2) as _,
-        width: dataset.dimensions[2] as _,
-        height: dataset.dimensions[1] as _,
-        encoding: "mono16".to_string(),
-        frame_id: Default::default(),
+        step: (width * pixel_size) as _,
+        width: width as _,
+        height: height as _,
+        encoding: encoding.to_string(),
+        frame_id: frame_id.to_string(),
     };
 
     let mut data = Vec::with_capacity(message.encoded_len().unwrap_or_default());
@@ -33,21 +98,32 @@ pub fn serialize_mono16_raw_image(index: u64, dataset: &Dataset) -> anyhow::Resu
     Ok(data)
 }
 
-pub fn serialize_rgb8_raw_image(index: u64, dataset: &Dataset) -> anyhow::Result<Vec<u8>> {
+pub fn serialize_rgb8_raw_image(
+    index: u64,
+    dataset: &Dataset,
+    frame_id: &str,
+    transposed: bool,
+) -> anyhow::Result<Vec<u8>> {
     if dataset.dimensions.len() != 4 {
         bail!("invalid dimensions for rgb8 image");
     }
 
-    let data = dataset.read_at_index::<u8>(index)?;
+    let (width, height) = image_size(dataset, transposed);
+
+    let mut data = dataset.read_at_index::<u8>(index)?;
+
+    if transposed {
+        data = swap_image_axes(data, width, height);
+    }
 
     let message = RawImage {
         timestamp: None,
         data: data.into(),
-        step: (dataset.dimensions[2] * 3) as _,
-        width: dataset.dimensions[2] as _,
-        height: dataset.dimensions[1] as _,
+        step: (width * 3) as _,
+        width: width as _,
+        height: height as _,
         encoding: "rgb8".to_string(),
-        frame_id: Default::default(),
+        frame_id: frame_id.to_string(),
     };
 
     let mut data = Vec::with_capacity(message.encoded_len().unwrap_or_default());
@@ -56,6 +132,36 @@ pub fn serialize_rgb8_raw_image(index: u64, dataset: &Dataset) -> anyhow::Result
     Ok(data)
 }
 
+/// Encoding used for the generic dataset topics.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageEncoding {
+    #[default]
+    Protobuf,
+    Json,
+}
+
+impl MessageEncoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Protobuf => "protobuf",
+            Self::Json => "json",
+        }
+    }
+}
+
+impl FromStr for MessageEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "protobuf" => Ok(Self::Protobuf),
+            "json" => Ok(Self::Json),
+            _ => bail!("unknown message encoding {s}"),
+        }
+    }
+}
+
 #[derive(Encode)]
 pub struct RawIntegerDataset {
     dimensions: Vec<u64>,
@@ -101,3 +207,421 @@ pub fn serialize_float_raw(index: u64, dataset: &Dataset) -> anyhow::Result<Vec<
 
     Ok(data)
 }
+
+/// Placement of a grid's cells in its frame, in (x, y) order where x follows the columns.
+#[derive(Debug, Clone)]
+pub struct GridGeometry {
+    frame_id: String,
+    cell_size: [f64; 2],
+    origin: [f64; 2],
+    // dimension scales may be descending (e.g. latitude from north to south)
+    flipped: [bool; 2],
+}
+
+impl GridGeometry {
+    /// Read the geometry from `cell_size`/`resolution` and `origin` attributes, falling back to
+    /// the coordinate values of the dimension scales attached to the row and column dimensions.
+    pub fn from_dataset(
+        dataset: &Dataset,
+        datasets: &BTreeMap<String, Dataset>,
+        frame_id: String,
+    ) -> anyhow::Result<Self> {
+        let mut geometry = Self {
+            frame_id,
+            cell_size: [1.0, 1.0],
+            origin: [0.0, 0.0],
+            flipped: [false, false],
+        };
+
+        let dimensions = dataset.dimension_scales(datasets);
+        let row_axes = dataset.row_axes();
+
+        // rows (y) then columns (x)
+        for (axis, dimension) in [(1, 0), (0, 1)] {
+            let Some(scale) = row_axes
+                .get(dimension)
+                .and_then(|x| dimensions.get(*x))
+                .and_then(|x| x.scale)
+            else {
+                continue;
+            };
+
+            let (values, _) = scale.read::<f64>()?;
+
+            let (Some(first), Some(last)) = (values.first(), values.last()) else {
+                continue;
+            };
+
+            if values.len() < 2 {
+                geometry.origin[axis] = *first;
+                continue;
+            }
+
+            let step = (last - first) / (values.len() - 1) as f64;
+
+            geometry.cell_size[axis] = step.abs();
+            geometry.origin[axis] = first.min(*last) - step.abs() / 2.0;
+            geometry.flipped[axis] = step < 0.0;
+        }
+
+        let cell_size = ["cell_size", "resolution"]
+            .iter()
+            .find_map(|name| dataset.attrs.get(*name).and_then(Attribute::as_f64s));
+
+        match cell_size.as_deref() {
+            Some([size]) => geometry.cell_size = [*size, *size],
+            Some([x, y, ..]) => geometry.cell_size = [*x, *y],
+            _ => {}
+        }
+
+        // a negative cell size is a descending axis, like rows from north to south
+        for axis in 0..2 {
+            if geometry.cell_size[axis] < 0.0 {
+                geometry.cell_size[axis] = -geometry.cell_size[axis];
+                geometry.flipped[axis] = true;
+            }
+        }
+
+        if let Some([x, y, ..]) = dataset
+            .attrs
+            .get("origin")
+            .and_then(Attribute::as_f64s)
+            .as_deref()
+        {
+            geometry.origin = [*x, *y];
+        }
+
+        Ok(geometry)
+    }
+}
+
+pub fn serialize_float_grid(
+    index: u64,
+    dataset: &Dataset,
+    geometry: &GridGeometry,
+) -> anyhow::Result<Vec<u8>> {
+    if dataset.dimensions.len() != 3 {
+        bail!("invalid dimensions for grid");
+    }
+
+    let rows = dataset.dimensions[1] as usize;
+    let columns = dataset.dimensions[2] as usize;
+
+    let values = dataset.read_at_index::<f32>(index)?;
+
+    let mut data = Vec::with_capacity(values.len() * 4);
+
+    for row in 0..rows {
+        let row = if geometry.flipped[1] {
+            rows - row - 1
+        } else {
+            row
+        };
+
+        for column in 0..columns {
+            let column = if geometry.flipped[0] {
+                columns - column - 1
+            } else {
+                column
+            };
+
+            data.extend_from_slice(&values[row * columns + column].to_le_bytes());
+        }
+    }
+
+    let message = Grid {
+        timestamp: None,
+        frame_id: geometry.frame_id.clone(),
+        pose: Some(Pose {
+            position: Some(Vector3 {
+                x: geometry.origin[0],
+                y: geometry.origin[1],
+                z: 0.0,
+            }),
+            orientation: Some(Quaternion {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+                w: 1.0,
+            }),
+        }),
+        column_count: columns as _,
+        cell_size: Some(Vector2 {
+            x: geometry.cell_size[0],
+            y: geometry.cell_size[1],
+        }),
+        row_stride: (columns * 4) as _,
+        cell_stride: 4,
+        fields: vec![PackedElementField {
+            name: "value".to_string(),
+            offset: 0,
+            r#type: NumericType::Float32 as _,
+        }],
+        data: data.into(),
+    };
+
+    let mut data = Vec::with_capacity(message.encoded_len().unwrap_or_default());
+    message.encode(&mut data)?;
+
+    Ok(data)
+}
+
+/// Angular span and range limits of a laser scan, plus the optional sibling intensities dataset.
+#[derive(Debug, Clone)]
+pub struct LaserScanParameters {
+    frame_id: String,
+    start_angle: f64,
+    end_angle: f64,
+    range_min: Option<f64>,
+    range_max: Option<f64>,
+    intensities: Option<Dataset>,
+}
+
+impl LaserScanParameters {
+    /// Read the scan parameters from the `angle_min`, `angle_max`, `range_min` and `range_max`
+    /// attributes. Intensities are taken from a `{name}.intensities` dataset or an `intensities`
+    /// dataset in the same group, when its shape matches the ranges.
+    pub fn from_dataset(
+        dataset: &Dataset,
+        datasets: &BTreeMap<String, Dataset>,
+        frame_id: String,
+    ) -> Option<Self> {
+        let attr = |name: &str| dataset.attrs.get(name).and_then(Attribute::as_f64);
+
+        let group = dataset.name.rsplit_once('/').map(|(group, _)| group)?;
+
+        let intensities = [
+            format!("{}.intensities", dataset.name),
+            format!("{group}/intensities"),
+        ]
+        .iter()
+        .filter_map(|name| datasets.get(name))
+        .find(|x| x.name != dataset.name && x.dimensions == dataset.dimensions)
+        .cloned();
+
+        Some(Self {
+            frame_id,
+            start_angle: attr("angle_min")?,
+            end_angle: attr("angle_max")?,
+            range_min: attr("range_min"),
+            range_max: attr("range_max"),
+            intensities,
+        })
+    }
+}
+
+pub fn serialize_laser_scan(
+    index: u64,
+    dataset: &Dataset,
+    parameters: &LaserScanParameters,
+) -> anyhow::Result<Vec<u8>> {
+    if dataset.dimensions.len() != 2 {
+        bail!("invalid dimensions for laser scan");
+    }
+
+    let mut ranges = dataset.read_at_index::<f64>(index)?;
+
+    // LaserScan has no range limits, so out of range returns are dropped from the render
+    for range in ranges.iter_mut() {
+        let below = parameters.range_min.is_some_and(|min| *range < min);
+        let above = parameters.range_max.is_some_and(|max| *range > max);
+
+        if below || above {
+            *range = f64::NAN;
+        }
+    }
+
+    let intensities = match &parameters.intensities {
+        Some(intensities) => intensities.read_at_index::<f64>(index)?,
+        None => vec![],
+    };
+
+    let message = LaserScan {
+        timestamp: None,
+        frame_id: parameters.frame_id.clone(),
+        pose: None,
+        start_angle: parameters.start_angle,
+        end_angle: parameters.end_angle,
+        ranges,
+        intensities,
+    };
+
+    let mut data = Vec::with_capacity(message.encoded_len().unwrap_or_default());
+    message.encode(&mut data)?;
+
+    Ok(data)
+}
+
+/// Foxglove only plays back 16-bit PCM, so float samples in the range [-1, 1] are converted.
+pub fn serialize_raw_audio(index: u64, dataset: &Dataset) -> anyhow::Result<Vec<u8>> {
+    if dataset.dimensions.len() != 2 && dataset.dimensions.len() != 3 {
+        bail!("invalid dimensions for audio");
+    }
+
+    let Some(sample_rate) = dataset.attrs.get("sample_rate").and_then(Attribute::as_f64) else {
+        bail!("missing sample_rate for audio");
+    };
+
+    let samples = match dataset.type_ {
+        DatasetType::Integer if dataset.type_size == 2 && dataset.signed => {
+            dataset.read_at_index::<i16>(index)?
+        }
+        DatasetType::Float => dataset
+            .read_at_index::<f32>(index)?
+            .into_iter()
+            .map(|x| (x.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+            .collect(),
+        t => bail!("unsupported audio sample type {t:?}"),
+    };
+
+    // samples are stored interleaved, one row per sample
+    let data: Vec<u8> = samples.iter().flat_map(|x| x.to_le_bytes()).collect();
+
+    let message = RawAudio {
+        timestamp: None,
+        data: data.into(),
+        format: "pcm-s16".to_string(),
+        sample_rate: sample_rate as _,
+        number_of_channels: dataset.dimensions.get(2).copied().unwrap_or(1) as _,
+    };
+
+    let mut data = Vec::with_capacity(message.encoded_len().unwrap_or_default());
+    message.encode(&mut data)?;
+
+    Ok(data)
+}
+
+/// A latitude dataset and the longitude dataset of the same shape in its group, like the
+/// `Latitude` and `Longitude` geolocation fields of HDF-EOS swaths.
+#[derive(Debug, Clone)]
+pub struct Geolocation {
+    frame_id: String,
+    longitude: Dataset,
+}
+
+impl Geolocation {
+    /// Pair a `latitude`, `Latitude` or `lat` dataset with its `longitude`, `Longitude` or `lon`
+    /// sibling.
+    pub fn from_dataset(
+        dataset: &Dataset,
+        datasets: &BTreeMap<String, Dataset>,
+        frame_id: String,
+    ) -> Option<Self> {
+        let (group, basename) = dataset.name.rsplit_once('/')?;
+
+        if !matches!(basename.to_lowercase().as_str(), "latitude" | "lat") {
+            return None;
+        }
+
+        let longitude = datasets
+            .range(format!("{group}/")..)
+            .take_while(|(name, _)| name.starts_with(&format!("{group}/")))
+            .map(|(_, x)| x)
+            .find(|x| {
+                let (_, basename) = x.name.rsplit_once('/').unwrap_or_default();
+
+                matches!(basename.to_lowercase().as_str(), "longitude" | "lon")
+                    && x.stored_dimensions == dataset.stored_dimensions
+            })?;
+
+        Some(Self {
+            frame_id,
+            longitude: longitude.clone(),
+        })
+    }
+}
+
+/// Whether a coordinate is a position rather than a fill value, like the -1e30 of HDF-EOS.
+fn is_valid_coordinate(latitude: f64, longitude: f64) -> bool {
+    latitude.abs() <= 90.0 && longitude.abs() <= 360.0
+}
+
+/// The position of a row, or of the middle of a scan line for rows of several positions.
+pub fn serialize_location_fix(
+    index: u64,
+    dataset: &Dataset,
+    geolocation: &Geolocation,
+) -> anyhow::Result<Vec<u8>> {
+    let latitudes = dataset.read_at_index::<f64>(index)?;
+    let longitudes = geolocation.longitude.read_at_index::<f64>(index)?;
+
+    let middle = latitudes.len() / 2;
+
+    let (Some(latitude), Some(longitude)) = (latitudes.get(middle), longitudes.get(middle)) else {
+        bail!("empty geolocation row");
+    };
+
+    let (latitude, longitude) = if is_valid_coordinate(*latitude, *longitude) {
+        (*latitude, *longitude)
+    } else {
+        (f64::NAN, f64::NAN)
+    };
+
+    let message = LocationFix {
+        timestamp: None,
+        frame_id: geolocation.frame_id.clone(),
+        latitude,
+        longitude,
+        altitude: 0.0,
+        position_covariance: vec![],
+        position_covariance_type: 0,
+    };
+
+    let mut data = Vec::with_capacity(message.encoded_len().unwrap_or_default());
+    message.encode(&mut data)?;
+
+    Ok(data)
+}
+
+/// The positions of a row as a GeoJSON line, like the footprint of a swath's scan line.
+pub fn serialize_geojson(
+    index: u64,
+    dataset: &Dataset,
+    geolocation: &Geolocation,
+) -> anyhow::Result<Vec<u8>> {
+    let latitudes = dataset.read_at_index::<f64>(index)?;
+    let longitudes = geolocation.longitude.read_at_index::<f64>(index)?;
+
+    let coordinates: Vec<[f64; 2]> = latitudes
+        .into_iter()
+        .zip(longitudes)
+        .filter(|(latitude, longitude)| is_valid_coordinate(*latitude, *longitude))
+        .map(|(latitude, longitude)| [longitude, latitude])
+        .collect();
+
+    let geojson = serde_json::json!({
+        "type": "Feature",
+        "properties": {},
+        "geometry": {
+            "type": "LineString",
+            "coordinates": coordinates,
+        },
+    });
+
+    let message = GeoJson {
+        geojson: geojson.to_string(),
+    };
+
+    let mut data = Vec::with_capacity(message.encoded_len().unwrap_or_default());
+    message.encode(&mut data)?;
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swap_image_axes_of_transposed_frames() {
+        // a 3x2 frame stored as (x, y): [[0, 1], [2, 3], [4, 5]]
+        assert_eq!(swap_image_axes((0..6).collect(), 3, 2), [0, 2, 4, 1, 3, 5]);
+
+        // the channels of each pixel stay together
+        let rgb: Vec<u8> = (0..12).collect();
+        assert_eq!(
+            swap_image_axes(rgb, 2, 2),
+            [0, 1, 2, 6, 7, 8, 3, 4, 5, 9, 10, 11]
+        );
+    }
+}
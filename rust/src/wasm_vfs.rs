@@ -1,6 +1,8 @@
 use hdf5_sys::*;
 
 use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
     ffi::{CStr, CString, c_char},
     fs::File,
     io::{BufReader, Read, Seek, SeekFrom},
@@ -9,6 +11,8 @@ use std::{
     usize,
 };
 
+use foxglove_data_loader::Problem;
+
 use crate::error;
 
 unsafe extern "C" fn vfs_hdf5_close(h: *mut H5FD_t) -> herr_t {
@@ -19,19 +23,332 @@ unsafe extern "C" fn vfs_hdf5_close(h: *mut H5FD_t) -> herr_t {
 
 trait InnerReader: Read + Seek {}
 
+#[cfg(target_arch = "wasm32")]
+type Reader = BufReader<foxglove_data_loader::reader::Reader>;
+#[cfg(not(target_arch = "wasm32"))]
+type Reader = BufReader<File>;
+
+fn open_reader(path: &str) -> std::io::Result<(Reader, u64)> {
+    #[cfg(target_arch = "wasm32")]
+    let reader = foxglove_data_loader::reader::open(path);
+    #[cfg(target_arch = "wasm32")]
+    let size = reader.size();
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let reader = std::fs::File::open(path)?;
+    #[cfg(not(target_arch = "wasm32"))]
+    let size = reader.metadata()?.len();
+
+    Ok((BufReader::new(reader), size))
+}
+
+/// One of the files backing the address space of a [`WasmFile`].
+struct Member {
+    reader: Reader,
+    size: u64,
+    // position of the member in its family
+    number: u64,
+    // address of the member's first byte
+    base: u64,
+}
+
+impl Member {
+    /// Read as much of `buffer` as the member holds from `offset`, returning the length read.
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> usize {
+        let pos = self.reader.stream_position().unwrap();
+
+        if pos != offset {
+            self.reader.seek(SeekFrom::Start(offset)).unwrap();
+        }
+
+        let mut pos = 0;
+
+        while pos < buffer.len() {
+            let written = self.reader.read(&mut buffer[pos..]).unwrap();
+
+            if written == 0 {
+                break;
+            }
+
+            pos += written;
+        }
+
+        pos
+    }
+}
+
 #[repr(C)]
 struct WasmFile {
     //
     parent: H5FD_t,
     // fields from here are private to this vfs
-    #[cfg(target_arch = "wasm32")]
-    reader: BufReader<foxglove_data_loader::reader::Reader>,
-    #[cfg(not(target_arch = "wasm32"))]
-    reader: BufReader<File>,
-    size: u64,
+    // a single file, the members of a family or the metadata and raw data files of a split
+    // file, by increasing base address
+    members: Vec<Member>,
     eoa: u64,
 }
 
+impl WasmFile {
+    fn size(&self) -> u64 {
+        self.members
+            .last()
+            .map(|x| x.base + x.size)
+            .unwrap_or_default()
+    }
+
+    /// Place the members of a family one after the other.
+    fn set_member_size(&mut self, member_size: u64) {
+        for member in self.members.iter_mut() {
+            member.base = member.number * member_size;
+        }
+    }
+}
+
+/// Files which HDF5 opens as one.
+#[derive(Debug, Clone)]
+pub enum FileSet {
+    Single(String),
+    /// Members of a file written with the family driver with their number, in order.
+    Family(Vec<(u64, String)>),
+    /// Metadata and raw data files written with the split driver.
+    Split {
+        meta: String,
+        raw: String,
+    },
+}
+
+impl FileSet {
+    /// The path the file set is opened with and named after.
+    pub fn path(&self) -> &str {
+        match self {
+            FileSet::Single(path) => path,
+            FileSet::Family(members) => &members[0].1,
+            FileSet::Split { meta, .. } => meta,
+        }
+    }
+}
+
+thread_local! {
+    // files backing a path opened by HDF5, for families and split files
+    static MEMBERS: RefCell<HashMap<String, Vec<(u64, String)>>> = Default::default();
+    // files which can be opened, other names are resolved against them
+    static AVAILABLE: RefCell<Vec<String>> = Default::default();
+}
+
+/// Set the files provided by the user. HDF5 asks for the files targeted by external links and
+/// virtual datasets under the names stored in the file, which are matched to these by file name.
+pub fn set_available_paths(paths: Vec<String>) {
+    AVAILABLE.set(paths);
+}
+
+/// The provided file to open for a name requested by HDF5.
+pub fn resolve_path(name: &str) -> Option<String> {
+    AVAILABLE.with_borrow(|paths| {
+        if paths.is_empty() || paths.iter().any(|x| x == name) {
+            return Some(name.to_string());
+        }
+
+        let file_name = |x: &str| x.rsplit(['/', '\\']).next().unwrap_or(x).to_string();
+
+        paths
+            .iter()
+            .find(|x| file_name(x) == file_name(name))
+            .cloned()
+    })
+}
+
+/// Open `members`, numbered by their position in a family, as one file when HDF5 opens the
+/// path of the first member with the [`WASM_FAMILY_VFS`] or [`WASM_SPLIT_VFS`] driver.
+pub fn register_members(members: Vec<(u64, String)>) {
+    if let Some((_, first)) = members.first().cloned() {
+        MEMBERS.with_borrow_mut(|x| x.insert(first, members));
+    }
+}
+
+/// Whether the file was written with the driver of the given name, which is stored in the
+/// driver information block following the superblock.
+fn has_driver_info(path: &str, driver: &[u8; 8]) -> bool {
+    let Ok((mut reader, size)) = open_reader(path) else {
+        return false;
+    };
+
+    let mut header = vec![0; size.min(4096) as usize];
+
+    if reader.read_exact(&mut header).is_err() {
+        return false;
+    }
+
+    header.windows(8).any(|x| x == driver)
+}
+
+pub fn is_family_file(path: &str) -> bool {
+    has_driver_info(path, b"NCSAfami")
+}
+
+/// Whether the file holds the metadata of a file written with the split or multi driver.
+pub fn is_split_file(path: &str) -> bool {
+    has_driver_info(path, b"NCSAmult")
+}
+
+/// Whether the file is a classic or 64-bit NetCDF-3 file, which isn't stored as HDF5.
+pub fn is_netcdf3(path: &str) -> bool {
+    let Ok((mut reader, _)) = open_reader(path) else {
+        return false;
+    };
+
+    let mut magic = [0; 4];
+
+    reader.read_exact(&mut magic).is_ok() && matches!(&magic, b"CDF\x01" | b"CDF\x02" | b"CDF\x05")
+}
+
+/// Whether the file starts with the HDF5 signature, at offset 0 or a power of two from 512, as
+/// the raw data file of a split file doesn't.
+fn has_signature(path: &str) -> bool {
+    let Ok((mut reader, size)) = open_reader(path) else {
+        return false;
+    };
+
+    let mut header = vec![0; size.min(4096) as usize];
+
+    if reader.read_exact(&mut header).is_err() {
+        return false;
+    }
+
+    [0, 512, 1024, 2048]
+        .into_iter()
+        .any(|x| header.get(x..x + 8) == Some(b"\x89HDF\r\n\x1a\n"))
+}
+
+/// Group the members of file families, named like `log_00000.h5`, `log_00001.h5`, etc., and
+/// the `name-m.h5` and `name-r.h5` files of split files. Other paths are returned as single
+/// files, and file sets which can't be opened for lack of some of their files as problems.
+pub fn group_files(paths: &[String]) -> Vec<Result<FileSet, Problem>> {
+    // members by the name around their number: its prefix, digit count and extension
+    let mut candidates: BTreeMap<_, Vec<(u64, String)>> = BTreeMap::new();
+    let mut out = vec![];
+
+    let split_raw = |meta: &str| {
+        let (stem, extension) = meta.rsplit_once('.').unwrap_or((meta, ""));
+        let raw = format!("{}-r.{extension}", stem.strip_suffix("-m")?);
+
+        paths.iter().find(|x| **x == raw).cloned()
+    };
+
+    let split_meta = |raw: &str| {
+        let (stem, extension) = raw.rsplit_once('.').unwrap_or((raw, ""));
+        Some(format!("{}-m.{extension}", stem.strip_suffix("-r")?))
+    };
+
+    for path in paths {
+        if is_netcdf3(path) {
+            out.push(Err(Problem::error(format!("Unsupported NetCDF-3 file {path}"))
+                .tip(format!("Only NetCDF-4 files, which are stored as HDF5, can be opened. Convert it with `nccopy -k nc4 {path} out.nc`."))));
+            continue;
+        }
+
+        if is_split_file(path) {
+            match split_raw(path) {
+                Some(raw) => out.push(Ok(FileSet::Split {
+                    meta: path.clone(),
+                    raw,
+                })),
+                None => out.push(Err(Problem::error(format!("Missing raw data file for {path}"))
+                    .tip("The file was written with the HDF5 split driver, open its -m.h5 and -r.h5 files together."))),
+            }
+            continue;
+        }
+
+        if let Some(meta) = split_meta(path)
+            && !paths.contains(&meta)
+            && !has_signature(path)
+        {
+            out.push(Err(Problem::error(format!("Missing split metadata file for {path}"))
+                .tip(format!("The file holds the raw data of a file written with the HDF5 split driver, open it together with {meta}."))));
+            continue;
+        }
+
+        if paths.iter().any(|x| split_raw(x).as_ref() == Some(path)) {
+            continue;
+        }
+
+        let name_start = path.rfind('/').map(|x| x + 1).unwrap_or(0);
+        let stem_end = path[name_start..]
+            .rfind('.')
+            .map(|x| name_start + x)
+            .unwrap_or(path.len());
+
+        let digits = path[..stem_end]
+            .bytes()
+            .rev()
+            .take_while(|x| x.is_ascii_digit())
+            .count();
+
+        let number_start = stem_end - digits;
+
+        match path[number_start..stem_end].parse::<u64>() {
+            Ok(number) => {
+                let key = (&path[..number_start], digits, &path[stem_end..]);
+                candidates
+                    .entry(key)
+                    .or_default()
+                    .push((number, path.clone()));
+            }
+            _ => out.push(Ok(FileSet::Single(path.clone()))),
+        }
+    }
+
+    for ((prefix, digits, extension), mut members) in candidates {
+        members.sort();
+
+        // only member 0 holds the superblock, the others have no signature to be recognized by
+        if members[0].0 != 0 && !members.iter().any(|(_, x)| has_signature(x)) {
+            out.push(Err(Problem::error(format!(
+                "Missing family member 0 for {}",
+                members[0].1
+            ))
+            .tip(format!(
+                "The file may have been split with the HDF5 family driver, open its first member {prefix}{:0digits$}{extension} together with the others.",
+                0
+            ))));
+            continue;
+        }
+
+        if !is_family_file(&members[0].1) {
+            out.extend(members.into_iter().map(|(_, x)| Ok(FileSet::Single(x))));
+            continue;
+        }
+
+        // members are addressed by their number, so every member up to the last is needed
+        let last = members.last().map(|(x, _)| *x).unwrap_or_default();
+
+        let missing: Vec<String> = (0..=last)
+            .filter(|x| !members.iter().any(|(number, _)| number == x))
+            .map(|x| x.to_string())
+            .collect();
+
+        if members.len() == 1 || !missing.is_empty() {
+            let tip = match missing.is_empty() {
+                true => "The file was split with the HDF5 family driver, open all of its members together.".to_string(),
+                false => format!(
+                    "The file was split with the HDF5 family driver, open its members numbered {} together with the others.",
+                    missing.join(", ")
+                ),
+            };
+
+            out.push(Err(Problem::error(format!(
+                "Missing family members for {}",
+                members[0].1
+            ))
+            .tip(tip)));
+            continue;
+        }
+
+        out.push(Ok(FileSet::Family(members)));
+    }
+
+    out
+}
+
 unsafe extern "C" fn vfs_hdf5_open(
     name: *const ::std::os::raw::c_char,
     flags: ::std::os::raw::c_uint,
@@ -42,19 +359,31 @@ unsafe extern "C" fn vfs_hdf5_open(
 
     let path = CStr::from_ptr(name).to_string_lossy();
 
-    #[cfg(target_arch = "wasm32")]
-    let reader = foxglove_data_loader::reader::open(&*path);
-    #[cfg(target_arch = "wasm32")]
-    let size = reader.size();
+    let paths = MEMBERS
+        .with_borrow(|x| x.get(&*path).cloned())
+        .unwrap_or_else(|| vec![(0, path.to_string())]);
 
-    #[cfg(not(target_arch = "wasm32"))]
-    let reader = std::fs::File::open(&*path).unwrap();
-    #[cfg(not(target_arch = "wasm32"))]
-    let size = reader.metadata().unwrap().len();
+    let mut members = vec![];
 
-    let reader = BufReader::new(reader);
+    for (number, path) in paths {
+        let Some(path) = resolve_path(&path) else {
+            return std::ptr::null_mut();
+        };
 
-    let file = Box::new(WasmFile {
+        let Ok((reader, size)) = open_reader(&path) else {
+            error!("failed to open {path}");
+            return std::ptr::null_mut();
+        };
+
+        members.push(Member {
+            reader,
+            size,
+            number,
+            base: 0,
+        });
+    }
+
+    let mut file = Box::new(WasmFile {
         parent: H5FD_t {
             driver_id: 0,
             cls: (&WASM_VFS) as *const _,
@@ -68,11 +397,17 @@ unsafe extern "C" fn vfs_hdf5_open(
             paged_aggr: false,
         },
 
-        reader,
-        size,
-        eoa: size,
+        members,
+        eoa: 0,
     });
 
+    // every member of a family but the last is full, until the superblock gives the member size
+    if let Some(member_size) = file.members.first().map(|x| x.size) {
+        file.set_member_size(member_size);
+    }
+
+    file.eoa = file.size();
+
     Box::leak(file) as *mut _ as *mut H5FD_t
 }
 
@@ -80,7 +415,11 @@ unsafe extern "C" fn vfs_get_eoa(file: *const H5FD_t, type_: H5F_mem_t) -> haddr
     // println!("getting eoa");
 
     let file: *const WasmFile = file as _;
-    (&*file).eoa
+    let file = &*file;
+
+    // reads are bounded by the members, so the raw data of a split file stays addressable
+    // whatever end of address space HDF5 sets from the metadata
+    file.eoa.max(file.size())
 }
 
 unsafe extern "C" fn vfs_set_eoa(file: *mut H5FD_t, type_: H5F_mem_t, addr: haddr_t) -> herr_t {
@@ -95,7 +434,87 @@ unsafe extern "C" fn vfs_get_eof(file: *const H5FD_t, type_: H5F_mem_t) -> haddr
     // println!("getting eof");
 
     let file: *const WasmFile = file as _;
-    (&*file).size
+    (&*file).size()
+}
+
+unsafe extern "C" fn vfs_family_sb_decode(
+    file: *mut H5FD_t,
+    _name: *const c_char,
+    buffer: *const ::std::os::raw::c_uchar,
+) -> herr_t {
+    // the family driver information is the member size as a little endian u64
+    let file = unsafe { &mut *(file as *mut WasmFile) };
+    let info = unsafe { std::slice::from_raw_parts(buffer, 8) };
+
+    file.set_member_size(u64::from_le_bytes(info.try_into().unwrap()));
+
+    0
+}
+
+unsafe extern "C" fn vfs_split_open(
+    name: *const ::std::os::raw::c_char,
+    flags: ::std::os::raw::c_uint,
+    fapl: hid_t,
+    maxaddr: haddr_t,
+) -> *mut H5FD_t {
+    let file = unsafe { vfs_hdf5_open(name, flags, fapl, maxaddr) };
+
+    if file.is_null() {
+        return file;
+    }
+
+    // the split driver places the raw data at half of the address space, until the
+    // superblock gives the address
+    let wasm_file = unsafe { &mut *(file as *mut WasmFile) };
+
+    if let [_, raw] = &mut wasm_file.members[..] {
+        raw.base = (u64::MAX - 1) / 2;
+    }
+
+    wasm_file.eoa = wasm_file.size();
+
+    file
+}
+
+unsafe extern "C" fn vfs_split_sb_decode(
+    file: *mut H5FD_t,
+    _name: *const c_char,
+    buffer: *const ::std::os::raw::c_uchar,
+) -> herr_t {
+    let file = unsafe { &mut *(file as *mut WasmFile) };
+
+    // the multi driver information starts with the member of each memory type, padded to 8
+    // bytes, followed by the address and end of address of each distinct member
+    let map = unsafe { std::slice::from_raw_parts(buffer, 8) };
+
+    let mut seen = vec![];
+
+    for (i, member) in map[..H5F_mem_t_H5FD_MEM_NTYPES as usize - 1]
+        .iter()
+        .enumerate()
+    {
+        let member = match *member as H5F_mem_t {
+            x if x == H5F_mem_t_H5FD_MEM_DEFAULT => i as H5F_mem_t + 1,
+            x => x,
+        };
+
+        if !seen.contains(&member) {
+            seen.push(member);
+        }
+    }
+
+    if seen.len() != file.members.len() {
+        error!("unsupported multi file with {} members", seen.len());
+        return -1;
+    }
+
+    let addresses = unsafe { std::slice::from_raw_parts(buffer.add(8), seen.len() * 16) };
+
+    for (member, address) in file.members.iter_mut().zip(addresses.chunks_exact(16)) {
+        member.base = u64::from_le_bytes(address[..8].try_into().unwrap());
+    }
+
+    0
 }
 
 unsafe extern "C" fn vfs_write(
@@ -120,35 +539,36 @@ unsafe extern "C" fn vfs_read(
 ) -> herr_t {
     // println!("calling read, addr: {addr}, size: {size}");
 
-    let wasm_file: *mut WasmFile = file as _;
-    let file = &mut *wasm_file;
-
-    let pos = file.reader.stream_position().unwrap();
+    let file = unsafe { &mut *(file as *mut WasmFile) };
 
-    if pos != addr {
-        file.reader.seek(SeekFrom::Start(addr)).unwrap();
-    }
-
-    let slice = std::slice::from_raw_parts_mut(buffer as *mut u8, size);
+    let slice = unsafe { std::slice::from_raw_parts_mut(buffer as *mut u8, size) };
     slice.fill(0);
 
     let mut pos = 0;
 
-    loop {
-        let written = (&mut *file)
-            .reader
-            .read(&mut slice[pos as usize..])
-            .unwrap();
+    // reads of a family may span several members
+    while pos < size {
+        let addr = addr + pos as u64;
 
-        if written == 0 {
+        let Some(index) = file.members.iter().rposition(|x| x.base <= addr) else {
             break;
-        }
+        };
 
-        pos += written;
+        let end = file
+            .members
+            .get(index + 1)
+            .map(|x| x.base)
+            .unwrap_or(u64::MAX);
+
+        let member = &mut file.members[index];
+        let len = (end - addr).min((size - pos) as u64) as usize;
+        let written = member.read_at(addr - member.base, &mut slice[pos..pos + len]);
 
-        if pos >= size {
+        if written == 0 {
             break;
         }
+
+        pos += written;
     }
 
     if pos == 0 { -1 } else { 0 }
@@ -215,3 +635,156 @@ pub const WASM_VFS: H5FD_class_t = H5FD_class_t {
     del: None,
     ctl: None,
 };
+
+/// The same driver under the name HDF5 expects for files written with the family driver, so
+/// their superblock is accepted and the member size decoded from it.
+pub const WASM_FAMILY_VFS: H5FD_class_t = H5FD_class_t {
+    name: c"family".as_ptr(),
+    value: 124,
+    sb_decode: Some(vfs_family_sb_decode),
+    ..WASM_VFS
+};
+
+/// The same driver under the name HDF5 expects for files written with the split driver, reading
+/// the metadata and raw data from their own files.
+pub const WASM_SPLIT_VFS: H5FD_class_t = H5FD_class_t {
+    name: c"multi".as_ptr(),
+    value: 125,
+    open: Some(vfs_split_open),
+    sb_decode: Some(vfs_split_sb_decode),
+    ..WASM_VFS
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIGNATURE: &[u8] = b"\x89HDF\r\n\x1a\n";
+
+    /// Write files to a new directory, returning their paths in order.
+    fn write_files(test: &str, files: &[(&str, &[u8])]) -> Vec<String> {
+        let dir = std::env::temp_dir().join(format!("hdf5-loader-{}-{test}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        files
+            .iter()
+            .map(|(name, contents)| {
+                let path = dir.join(name);
+                std::fs::write(&path, contents).unwrap();
+                path.to_string_lossy().into_owned()
+            })
+            .collect()
+    }
+
+    fn header(driver: &[u8]) -> Vec<u8> {
+        [SIGNATURE, &[0; 56], driver, &[0; 64]].concat()
+    }
+
+    fn paths_of(sets: &[Result<FileSet, Problem>]) -> Vec<&str> {
+        sets.iter()
+            .filter_map(|x| x.as_ref().ok())
+            .map(FileSet::path)
+            .collect()
+    }
+
+    #[test]
+    fn family_members_are_grouped() {
+        let paths = write_files(
+            "family",
+            &[
+                ("log_00000.h5", &header(b"NCSAfami")),
+                ("log_00001.h5", &[0; 16]),
+                ("log_00002.h5", &[0; 16]),
+                ("other.h5", SIGNATURE),
+            ],
+        );
+
+        let sets = group_files(&paths);
+        assert_eq!(sets.len(), 2);
+        assert!(matches!(&sets[0], Ok(FileSet::Single(x)) if *x == paths[3]));
+
+        let Ok(FileSet::Family(members)) = &sets[1] else {
+            panic!("not a family");
+        };
+        assert_eq!(
+            members.iter().map(|(x, _)| *x).collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+        assert!(members.iter().zip(&paths).all(|((_, x), path)| x == path));
+    }
+
+    #[test]
+    fn incomplete_families_are_reported() {
+        let paths = write_files(
+            "gap",
+            &[
+                ("log_00000.h5", &header(b"NCSAfami")),
+                ("log_00002.h5", &[0; 16]),
+            ],
+        );
+        assert!(matches!(&group_files(&paths)[..], [Err(_)]));
+
+        let paths = write_files("lone", &[("log_00000.h5", &header(b"NCSAfami"))]);
+        assert!(matches!(&group_files(&paths)[..], [Err(_)]));
+    }
+
+    #[test]
+    fn numbered_files_of_other_drivers_are_single() {
+        let paths = write_files(
+            "numbered",
+            &[("run_1.h5", SIGNATURE), ("run_2.h5", SIGNATURE)],
+        );
+
+        let sets = group_files(&paths);
+        assert_eq!(paths_of(&sets), [&*paths[0], &*paths[1]]);
+    }
+
+    #[test]
+    fn split_files_are_paired() {
+        let paths = write_files(
+            "split",
+            &[("scan-m.h5", &header(b"NCSAmult")), ("scan-r.h5", &[0; 16])],
+        );
+
+        let sets = group_files(&paths);
+        assert!(
+            matches!(&sets[..], [Ok(FileSet::Split { meta, raw })] if *meta == paths[0] && *raw == paths[1])
+        );
+    }
+
+    #[test]
+    fn split_files_without_their_pair_are_reported() {
+        let paths = write_files("split-m", &[("scan-m.h5", &header(b"NCSAmult"))]);
+        assert!(matches!(&group_files(&paths)[..], [Err(_)]));
+
+        let paths = write_files("split-r", &[("scan-r.h5", &[0; 16])]);
+        assert!(matches!(&group_files(&paths)[..], [Err(_)]));
+    }
+
+    #[test]
+    fn hdf5_files_named_like_raw_data_are_single() {
+        let paths = write_files("named-r", &[("left-r.h5", SIGNATURE)]);
+        assert_eq!(paths_of(&group_files(&paths)), [&*paths[0]]);
+    }
+
+    #[test]
+    fn netcdf3_files_are_reported() {
+        let paths = write_files(
+            "netcdf3",
+            &[("classic.nc", b"CDF\x01\0\0\0\0"), ("data.nc", SIGNATURE)],
+        );
+
+        let sets = group_files(&paths);
+        assert!(matches!(&sets[..], [Err(_), Ok(FileSet::Single(x))] if *x == paths[1]));
+    }
+
+    #[test]
+    fn families_without_their_first_member_are_reported() {
+        let paths = write_files(
+            "no-first",
+            &[("log_00001.h5", &[0; 16]), ("log_00002.h5", &[0; 16])],
+        );
+        assert!(matches!(&group_files(&paths)[..], [Err(_)]));
+    }
+}
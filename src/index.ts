@@ -3,10 +3,27 @@ import { Experimental } from "@foxglove/extension";
 // Import the .wasm file as a base64 data URL to be bundled with the extension
 import wasmUrl from "../rust/target/wasm32-wasip1/release/foxglove_data_loader.wasm";
 
+// NetCDF-4, HDF-EOS5, NWB and MATLAB v7.3 files are HDF5 files following their own conventions.
+// Mapping files are opened together with them, and have their own extensions so other JSON and
+// TOML files are left to other loaders.
+const SUPPORTED_FILE_TYPES = [
+  ".h5",
+  ".hdf5",
+  ".he5",
+  ".nc",
+  ".nc4",
+  ".nwb",
+  ".mat",
+  ".h5map.json",
+  ".h5map.toml",
+];
+
 export function activate(extensionContext: Experimental.ExtensionContext): void {
-  extensionContext.registerDataLoader({
-    type: "file",
-    wasmUrl,
-    supportedFileType: ".h5",
-  });
+  for (const supportedFileType of SUPPORTED_FILE_TYPES) {
+    extensionContext.registerDataLoader({
+      type: "file",
+      wasmUrl,
+      supportedFileType,
+    });
+  }
 }